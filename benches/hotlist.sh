@@ -0,0 +1,47 @@
+#!/usr/bin/env bash
+#
+# Per-invocation latency of `slackrope hotlist`, e.g as run by a tmux status line.
+#
+# usage: benches/hotlist.sh [RUNS] [BINARY...]
+#   benches/hotlist.sh 200 /tmp/slackrope-before ./target/release/slackrope
+#
+# Uses hyperfine when installed, or else a plain loop (mean, min and max in ms).
+# Weechat does not need to run: the cost measured is slackrope's own startup,
+# up to the relay connection.
+
+set -euo pipefail
+
+RUNS="${1:-100}"
+shift || true
+BINARIES=("$@")
+if [ ${#BINARIES[@]} -eq 0 ]; then
+  BINARIES=("./target/release/slackrope")
+fi
+
+if command -v hyperfine >/dev/null; then
+  hyperfine --warmup 5 --runs "$RUNS" --shell=none \
+    --ignore-failure "${BINARIES[@]/%/ hotlist}"
+  exit 0
+fi
+
+for binary in "${BINARIES[@]}"; do
+  # warmup
+  for _ in 1 2 3 4 5; do "$binary" hotlist >/dev/null || true; done
+
+  total=0
+  min=""
+  max=0
+  for _ in $(seq "$RUNS"); do
+    start=$(date +%s%N)
+    "$binary" hotlist >/dev/null || true
+    elapsed=$(( $(date +%s%N) - start ))
+    total=$(( total + elapsed ))
+    if [ -z "$min" ] || [ "$elapsed" -lt "$min" ]; then min=$elapsed; fi
+    if [ "$elapsed" -gt "$max" ]; then max=$elapsed; fi
+  done
+
+  awk -v b="$binary" -v r="$RUNS" -v t="$total" -v mi="$min" -v ma="$max" 'BEGIN {
+    printf "%s hotlist (%d runs): mean %.2f ms, min %.2f ms, max %.2f ms\n",
+      b, r, t / r / 1e6, mi / 1e6, ma / 1e6
+  }'
+done
@@ -1,4 +1,7 @@
 use clap::{Parser, Subcommand, ValueEnum};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about = None)]
@@ -8,6 +11,26 @@ pub struct Cli {
     #[arg(short, long, action = clap::ArgAction::Count)]
     pub debug: u8,
 
+    /// Configuration file to use instead of slackrope.toml [env: SLACKROPE_CONFIG]
+    #[arg(long, value_name = "PATH", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Profile to use, a [profiles.<name>] table of slackrope.toml [env: SLACKROPE_PROFILE]
+    #[arg(long, value_name = "NAME", global = true)]
+    pub profile: Option<String>,
+
+    /// Host weechat is running on [env: SLACKROPE_WEECHAT_HOST]
+    #[arg(long, value_name = "HOST", global = true)]
+    pub host: Option<String>,
+
+    /// Port weechat-relay is listening on [env: SLACKROPE_WEECHAT_RELAY_PORT]
+    #[arg(long, value_name = "PORT", global = true)]
+    pub port: Option<u16>,
+
+    /// Weechat executable [env: SLACKROPE_WEECHAT_PROGRAM_NAME]
+    #[arg(long, value_name = "PROGRAM", global = true)]
+    pub program: Option<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -33,21 +56,207 @@ pub enum Commands {
         /// Note that you can use ANSI escape codes e.g "\e[38;5;208m󰁥\e[0m{{priority_1}}"
         #[arg(short, long, value_name = "TEMPLATE", verbatim_doc_comment)]
         template: Option<String>,
+        /// Combine the hotlists of several profiles, e.g "work,perso" [default: all profiles]
+        /// An unreachable profile is reported with a "!" marker (see {{partial}} in templates)
+        #[arg(
+            short,
+            long,
+            value_name = "PROFILES",
+            value_delimiter = ',',
+            num_args = 0..,
+            conflicts_with = "start",
+            verbatim_doc_comment
+        )]
+        aggregate: Option<Vec<String>>,
     },
     /// Clear the current hotlist (sets all counters to 0)
     Clear,
-    /// Kill weechat daemon
+    /// Start weechat daemon, and wait until its relay is reachable
+    Start,
+    /// Stop weechat daemon gracefully (/quit, then SIGTERM, then SIGKILL)
+    Stop {
+        /// Time to wait for weechat to quit, before each escalation
+        #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+    },
+    /// Stop then start weechat daemon
+    Restart {
+        /// Time to wait for weechat to quit, before each escalation
+        #[arg(long, value_name = "DURATION", default_value = "5s", value_parser = humantime::parse_duration)]
+        timeout: Duration,
+    },
+    /// Print weechat daemon status (pid, uptime, relay, teams)
+    Status,
+    /// Kill weechat daemon (SIGKILL)
     Kill,
+    /// Adopt a weechat instance slackrope did not start, so that it manages it
+    Adopt {
+        /// Pid of the weechat process to adopt
+        #[arg(
+            long,
+            required_unless_present = "relay_port",
+            conflicts_with = "relay_port"
+        )]
+        pid: Option<u32>,
+        /// Adopt the weechat process listening on the configured relay port
+        #[arg(long)]
+        relay_port: bool,
+    },
+    /// Manage a systemd user service running weechat (Linux)
+    Service {
+        #[arg(value_name = "ACTION", value_enum)]
+        action: ServiceAction,
+    },
+    /// Manage slackrope's configuration file
+    Config {
+        #[arg(value_name = "ACTION", value_enum)]
+        action: ConfigAction,
+        /// Overwrite an existing configuration file (init)
+        #[arg(short, long, action)]
+        force: bool,
+    },
+    /// Manage the wee-slack python plugin
+    Plugin {
+        #[arg(value_name = "ACTION", value_enum)]
+        action: PluginAction,
+        /// The wee_slack.py file to install (install)
+        #[arg(value_name = "PATH", required_if_eq("action", "install"))]
+        path: Option<PathBuf>,
+        /// Install a file which does not look like wee-slack, replace another autoload file
+        #[arg(short, long, action)]
+        force: bool,
+    },
+    /// Configure the weechat relay for slackrope (port and password), then test it
+    SetupRelay {
+        /// Also add a TLS relay on this port, for other clients (weechat >= 4.0, needs a certificate)
+        #[arg(long, value_name = "PORT")]
+        tls: Option<u16>,
+        /// Also add a relay on this UNIX socket, for other clients
+        #[arg(long, value_name = "PATH")]
+        unix: Option<String>,
+        /// IP address the relay listens on, e.g "127.0.0.1"
+        #[arg(long, value_name = "ADDRESS")]
+        bind: Option<IpAddr>,
+        /// Edit relay.conf and sec.conf directly instead of running weechat (weechat is not started)
+        #[arg(long)]
+        offline: bool,
+    },
     /// List registered slack teams
     ListTeams,
-    /// Register a new slack team
+    /// Register a new slack team (prints the slack link, then prompts for what slack redirects to)
     Register {
-        /// (Optional) use only after you got your token from slack
+        /// (Optional) the address slack redirected you to, its code, or a token (xoxp-..., xoxc-...:xoxd-...)
+        #[arg(short, long, value_name = "REDIRECT_URL|CODE|TOKEN")]
+        token: Option<String>,
+        /// Read the value from stdin instead (out of the shell history and of ps)
+        #[arg(long, action, conflicts_with_all = ["token", "token_file"])]
+        token_stdin: bool,
+        /// Read the value from a file only you can read (chmod 600)
+        #[arg(long, value_name = "PATH", conflicts_with = "token")]
+        token_file: Option<PathBuf>,
+    },
+    /// List, remove or rotate the slack tokens of wee-slack (weechat keeps running)
+    Tokens {
+        #[arg(value_name = "ACTION", value_enum)]
+        action: TokensAction,
+        /// The team, or the token number printed by 'tokens list' (remove, rotate)
+        #[arg(value_name = "TEAM", required_if_eq_any([("action", "remove"), ("action", "rotate")]))]
+        team: Option<String>,
+        /// The new token (rotate)
         #[arg(short, long, value_name = "TOKEN")]
         token: Option<String>,
+        /// Read the new token from stdin (rotate)
+        #[arg(long, action, conflicts_with_all = ["token", "token_file"])]
+        token_stdin: bool,
+        /// Read the new token from a file only you can read (rotate)
+        #[arg(long, value_name = "PATH", conflicts_with = "token")]
+        token_file: Option<PathBuf>,
     },
     /// Print health report
     Health,
+    /// Print the next buffer to handle (most urgent first, like weechat's Alt-A)
+    Next {
+        /// Also print the unread lines of the buffer
+        #[arg(short, long, action)]
+        show: bool,
+        /// Mark the buffer as read afterwards
+        #[arg(short, long, action)]
+        mark_read: bool,
+    },
+    /// Mute a buffer or a whole workspace: it won't be counted in the hotlist
+    Mute {
+        /// A buffer (e.g "workspace.#channel") or a workspace (e.g "workspace")
+        #[arg(value_name = "BUFFER|WORKSPACE")]
+        target: String,
+        /// [default: until unmuted] mute duration, e.g "2h", "90m"
+        #[arg(long = "for", value_name = "DURATION", value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+        /// Also mute it in weechat (sets the buffer notify level to none)
+        #[arg(short, long, action)]
+        notify: bool,
+    },
+    /// Unmute a buffer or a workspace
+    Unmute {
+        #[arg(value_name = "BUFFER|WORKSPACE")]
+        target: String,
+    },
+    /// List muted buffers and workspaces
+    Mutes,
+    /// Lower weechat notify levels on slack buffers for a while (deep-work time)
+    Focus {
+        #[arg(value_name = "STATE", default_value_t = FocusState::On, value_enum)]
+        state: FocusState,
+        /// [default: until 'focus off'] focus duration, e.g "90m", "2h"
+        #[arg(long = "for", value_name = "DURATION", value_parser = humantime::parse_duration)]
+        duration: Option<Duration>,
+        /// Categories which still notify, e.g "dm,highlight"
+        #[arg(
+            short,
+            long,
+            value_name = "CATEGORIES",
+            value_delimiter = ',',
+            value_enum
+        )]
+        allow: Vec<FocusAllow>,
+    },
+    /// Set the weechat notify level of a buffer
+    Notify {
+        /// e.g "workspace.#channel"
+        #[arg(value_name = "BUFFER")]
+        buffer: String,
+        #[arg(value_name = "LEVEL", value_enum)]
+        level: NotifyLevel,
+    },
+    /// Print the last lines of one or many buffers, and optionally follow them
+    Tail {
+        /// Buffers to print, e.g "workspace.#channel"
+        #[arg(value_name = "BUFFER", required_unless_present = "workspace")]
+        buffers: Vec<String>,
+        /// Also print all the buffers of a slack workspace
+        #[arg(short, long, value_name = "WORKSPACE")]
+        workspace: Vec<String>,
+        /// Number of lines to print for each buffer
+        #[arg(short = 'n', long, value_name = "LINES", default_value_t = 10)]
+        lines: usize,
+        /// Keep printing new lines as they arrive
+        #[arg(short, long, action)]
+        follow: bool,
+        #[arg(
+            short = 'o',
+            long,
+            value_name = "FORMAT",
+            num_args = 1,
+            default_value_t = TailFormat::Plain,
+            value_enum
+        )]
+        format: TailFormat,
+    },
+    /// Browse the hotlist interactively (e.g in a tmux popup)
+    Ui {
+        /// Number of lines to preview for the selected buffer
+        #[arg(short = 'n', long, value_name = "LINES", default_value_t = 20)]
+        lines: usize,
+    },
 }
 
 #[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
@@ -59,3 +268,89 @@ pub enum OutputFormat {
     /// Detailed format: json
     Detailed,
 }
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TailFormat {
+    /// Plain text
+    Plain,
+    /// Text with ANSI colors
+    Color,
+    /// One json object per line
+    Json,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ServiceAction {
+    /// Write the unit file, then enable and start the service
+    Install,
+    /// Stop and disable the service, then remove the unit file
+    Uninstall,
+    /// Print whether the service is installed and active
+    Status,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ConfigAction {
+    /// Write a configuration file with every key at its default value
+    Init,
+    /// Print the effective configuration, and where each value comes from
+    Show,
+    /// Check the configuration file, reporting every error
+    Validate,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PluginAction {
+    /// Print where wee-slack is installed, whether it autoloads, its python dependency and its loaded version
+    Status,
+    /// Copy a local wee_slack.py into weechat's python directory
+    Install,
+    /// Let weechat load wee-slack when it starts (symlink in python/autoload)
+    Autoload,
+    /// Load wee-slack in the running weechat, or reload it
+    Reload,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TokensAction {
+    /// Print each token (masked) and the team it connects
+    List,
+    /// Remove the token of a team, then reload wee-slack
+    Remove,
+    /// Replace the token of a team, then reload wee-slack
+    Rotate,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FocusState {
+    /// Start focus mode
+    On,
+    /// Stop focus mode, restoring the previous notify levels
+    Off,
+    /// Print whether focus mode is on
+    Status,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FocusAllow {
+    /// Private conversations
+    Dm,
+    /// Highlights, in any buffer
+    Highlight,
+    /// Slack threads
+    Thread,
+}
+
+#[derive(ValueEnum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NotifyLevel {
+    /// Never add the buffer to the hotlist
+    None,
+    /// Add the buffer to the hotlist for highlights only
+    Highlight,
+    /// Add the buffer to the hotlist for highlights and messages
+    Message,
+    /// Add the buffer to the hotlist for any message
+    All,
+    /// Reset to the default level
+    Reset,
+}
@@ -0,0 +1,96 @@
+pub use crate::cli::ConfigAction;
+use crate::environment::{
+    config, get_config_path, get_unknown_env_variables, load_config, Config, CONFIG_KEYS,
+};
+use anyhow::{Context, Result};
+use std::fs;
+use std::process::exit;
+
+pub fn config_command(action: ConfigAction, force: bool) -> Result<()> {
+    match action {
+        ConfigAction::Init => init_config(force),
+        ConfigAction::Show => show_config(),
+        ConfigAction::Validate => validate_config(),
+    }
+}
+
+/*
+* Writes slackrope.toml with every key commented out, at its default value
+*/
+fn init_config(force: bool) -> Result<()> {
+    let path = get_config_path();
+    if path.exists() && !force {
+        println!(
+            "Error : {} already exists (use --force to overwrite it)",
+            path.display()
+        );
+        exit(1);
+    }
+    let mut lines = vec![
+        "# slackrope configuration".to_string(),
+        "# uncomment a key to override its default value".to_string(),
+    ];
+    let defaults = Config::default().entries();
+    for ((key, description), (_, value)) in CONFIG_KEYS.iter().zip(defaults) {
+        lines.push(String::new());
+        lines.push(format!("# {description}"));
+        lines.push(format!("# {key} = {value}"));
+    }
+    lines.push(String::new());
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    fs::write(&path, lines.join("\n")).with_context(|| format!("writing {}", path.display()))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/*
+* The effective configuration, and where each value comes from
+*/
+fn show_config() -> Result<()> {
+    let config = config();
+    match &config.file {
+        Some(path) => println!("# {}", path.display()),
+        None => println!(
+            "# no config file, {} not found",
+            get_config_path().display()
+        ),
+    }
+    if !config.profiles.is_empty() {
+        println!(
+            "# profile: {} (profiles: {})",
+            config.profile.as_deref().unwrap_or("none"),
+            config.profiles.join(", ")
+        );
+    }
+    for (key, value) in config.entries() {
+        println!("{key} = {value}  # {}", config.source(key));
+    }
+    Ok(())
+}
+
+fn validate_config() -> Result<()> {
+    for var in get_unknown_env_variables() {
+        println!("Warning : {var} is not a config variable, it is ignored");
+    }
+    match load_config() {
+        Ok(config) => {
+            match config.file {
+                Some(path) => println!("{} is valid", path.display()),
+                None => println!(
+                    "{} not found, default values apply",
+                    get_config_path().display()
+                ),
+            }
+            Ok(())
+        }
+        Err(errors) => {
+            for error in errors {
+                println!("Error : {error}");
+            }
+            exit(1);
+        }
+    }
+}
@@ -1,88 +1,951 @@
-use config::Config;
-use std::{path::Path, process::exit, sync::OnceLock};
+use anyhow::Context;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::fmt;
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::OnceLock,
+};
 
 pub const XDG_CONFIG_HOME: &str = "XDG_CONFIG_HOME";
+pub const XDG_STATE_HOME: &str = "XDG_STATE_HOME";
 pub const APP_NAME: &str = "slackrope";
-pub const CONFIG_FILE: &str = "sr_config_file";
-pub const WEECHAT_PROGRAM_NAME: &str = "sr_weechat_program_name";
-pub const WEECHAT_HOST: &str = "sr_weechat_host";
-pub const WEECHAT_RELAY_PORT: &str = "sr_weechat_relay_port";
-pub const WEECHAT_PASSWORD: &str = "sr_weechat_password";
-pub const SLACK_REGISTER_BASEURL: &str = "sr_slack_register_baseurl";
-pub const SLACK_REGISTER_WEESLACK_CLIENT_ID: &str = "sr_slack_register_weeslack_client_id";
-pub const SLACK_REGISTER_SCOPE: &str = "sr_slack_register_scope";
-pub const SLACK_REGISTER_REDIRECT_URI: &str = "sr_slack_register_redirect_uri";
-pub const WEE_SLACK_PLUGIN_DIRECTORY: &str = "sr_wee_slack_plugin_directory";
-pub const WEE_SLACK_PLUGIN_FILENAME: &str = "sr_wee_slack_plugin_filename";
-
-fn config() -> &'static Config {
-    static CONFIG: OnceLock<Config> = OnceLock::new();
-    CONFIG.get_or_init(|| {
-        let home_dir = get_home_dir().display().to_string();
-        let config_dir = match std::env::var(XDG_CONFIG_HOME) {
-            Ok(dir) => format!("{dir}/{APP_NAME}"),
-            Err(_) => format!("{home_dir}/.config/{APP_NAME}"),
+/* every key can be overridden by SLACKROPE_<KEY>, e.g SLACKROPE_WEECHAT_HOST */
+pub const ENV_PREFIX: &str = "SLACKROPE_";
+pub const ENV_CONFIG_FILE: &str = "SLACKROPE_CONFIG";
+pub const ENV_PROFILE: &str = "SLACKROPE_PROFILE";
+/* top-level keys of slackrope.toml, next to the sr_* keys */
+pub const DEFAULT_PROFILE_KEY: &str = "sr_default_profile";
+pub const PROFILES_KEY: &str = "profiles";
+
+/*
+* Every configuration key, in the order of `config init` and `config show`,
+* with its description
+*/
+pub const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("sr_weechat_host", "the host weechat is running on"),
+    ("sr_weechat_relay_port", "the port weechat-relay is listening on"),
+    (
+        "sr_weechat_password",
+        "the password needed to connect to weechat via weechat-relay, or a reference to weechat's secured data, e.g \"${sec.data.relay}\"",
+    ),
+    (
+        "sr_weechat_password_command",
+        "a command printing the relay password, e.g \"pass show weechat/relay\"",
+    ),
+    (
+        "sr_weechat_password_file",
+        "a file holding the relay password, readable by you only",
+    ),
+    (
+        "sr_weechat_program_name",
+        "the weechat executable, weechat or weechat-headless",
+    ),
+    (
+        "sr_weechat_mode",
+        "local, remote (weechat runs on another machine) or auto (remote unless the host is a loopback address)",
+    ),
+    (
+        "sr_weechat_dir",
+        "a dedicated weechat profile directory (--dir), empty means weechat's default directories",
+    ),
+    ("sr_weechat_args", "extra weechat arguments, e.g [\"--stdout\"]"),
+    (
+        "sr_weechat_startup_commands",
+        "weechat commands run after startup (one -r each)",
+    ),
+    (
+        "sr_weechat_env",
+        "environment variables for weechat, e.g [\"PYTHONPATH=/opt/python\"]",
+    ),
+    (
+        "sr_weechat_working_dir",
+        "the weechat working directory, empty means the current directory (the home directory for the systemd service)",
+    ),
+    ("sr_weechat_log_file", "where weechat output is captured"),
+    (
+        "sr_weechat_log_max_size",
+        "the log file is rotated once it exceeds this size (bytes), when weechat starts or on 'slackrope start'",
+    ),
+    (
+        "sr_slack_register_baseurl",
+        "needed to register your slack workspace",
+    ),
+    (
+        "sr_slack_register_weeslack_client_id",
+        "needed to register your slack workspace",
+    ),
+    (
+        "sr_slack_register_scope",
+        "needed to register your slack workspace",
+    ),
+    (
+        "sr_slack_register_redirect_uri",
+        "needed to register your slack workspace",
+    ),
+    (
+        "sr_wee_slack_plugin_directory",
+        "the wee-slack python plugins directory (empty: the python directory of weechat's data directory)",
+    ),
+    ("sr_wee_slack_plugin_filename", "the wee-slack plugin file"),
+];
+
+pub const SECRET_KEYS: &[&str] = &["sr_weechat_password"];
+const SECRET_MASK: &str = "********";
+
+/* where weechat is reached: each profile sets its own, see load_profile_config */
+const ENDPOINT_KEYS: &[&str] = &["sr_weechat_host", "sr_weechat_relay_port"];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "sr_weechat_host")]
+    pub weechat_host: String,
+    #[serde(
+        rename = "sr_weechat_relay_port",
+        deserialize_with = "deserialize_port"
+    )]
+    pub weechat_relay_port: u16,
+    #[serde(rename = "sr_weechat_password")]
+    pub weechat_password: String,
+    #[serde(rename = "sr_weechat_password_command")]
+    pub weechat_password_command: String,
+    #[serde(rename = "sr_weechat_password_file")]
+    pub weechat_password_file: String,
+    #[serde(rename = "sr_weechat_program_name")]
+    pub weechat_program_name: String,
+    #[serde(rename = "sr_weechat_mode")]
+    pub weechat_mode: WeechatMode,
+    #[serde(rename = "sr_weechat_dir")]
+    pub weechat_dir: String,
+    #[serde(rename = "sr_weechat_args")]
+    pub weechat_args: Vec<String>,
+    #[serde(rename = "sr_weechat_startup_commands")]
+    pub weechat_startup_commands: Vec<String>,
+    #[serde(rename = "sr_weechat_env")]
+    pub weechat_env: Vec<String>,
+    #[serde(rename = "sr_weechat_working_dir")]
+    pub weechat_working_dir: String,
+    #[serde(rename = "sr_weechat_log_file")]
+    pub weechat_log_file: String,
+    #[serde(rename = "sr_weechat_log_max_size")]
+    pub weechat_log_max_size: u64,
+    #[serde(rename = "sr_slack_register_baseurl")]
+    pub slack_register_baseurl: String,
+    #[serde(rename = "sr_slack_register_weeslack_client_id")]
+    pub slack_register_weeslack_client_id: String,
+    #[serde(rename = "sr_slack_register_scope")]
+    pub slack_register_scope: String,
+    #[serde(rename = "sr_slack_register_redirect_uri")]
+    pub slack_register_redirect_uri: String,
+    #[serde(rename = "sr_wee_slack_plugin_directory")]
+    pub wee_slack_plugin_directory: String,
+    #[serde(rename = "sr_wee_slack_plugin_filename")]
+    pub wee_slack_plugin_filename: String,
+
+    /* where each value comes from */
+    #[serde(skip)]
+    pub sources: HashMap<String, ConfigSource>,
+    /* the config file, when there is one */
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+    /* the selected profile, if any */
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /* every profile of the config file */
+    #[serde(skip)]
+    pub profiles: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WeechatMode {
+    Auto,
+    Local,
+    Remote,
+}
+
+/*
+* The layer an effective value comes from, the last one wins:
+* default, then the config file, then the environment, then the command line
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    /* the config file, at this line */
+    File(usize),
+    /* a [profiles.<name>] table of the config file, at this line */
+    Profile(String, usize),
+    /* this environment variable */
+    Env(String),
+    /* this command line flag */
+    Flag(&'static str),
+}
+
+impl ConfigSource {
+    pub fn is_file(&self) -> bool {
+        matches!(self, ConfigSource::File(_) | ConfigSource::Profile(..))
+    }
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(line) => write!(f, "file, line {line}"),
+            ConfigSource::Profile(name, line) => write!(f, "file, profile {name}, line {line}"),
+            ConfigSource::Env(var) => write!(f, "env {var}"),
+            ConfigSource::Flag(flag) => write!(f, "flag {flag}"),
+        }
+    }
+}
+
+/*
+* Values given on the command line, on top of the config file and the environment
+*/
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    pub config_file: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub program: Option<String>,
+}
+
+static OVERRIDES: OnceLock<ConfigOverrides> = OnceLock::new();
+
+/*
+* To be called before the first config() call
+*/
+pub fn set_config_overrides(overrides: ConfigOverrides) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+/*
+* A configuration error, with its origin: a file (and line), a variable or a flag
+*/
+#[derive(Debug)]
+pub struct ConfigError {
+    pub origin: String,
+    pub line: Option<usize>,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.origin)?;
+        if let Some(line) = self.line {
+            write!(f, ":{line}")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, ": {key}")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            weechat_host: "127.0.0.1".to_string(),
+            weechat_relay_port: 8000,
+            weechat_password: String::new(),
+            weechat_password_command: String::new(),
+            weechat_password_file: String::new(),
+            weechat_program_name: "weechat-headless".to_string(),
+            weechat_mode: WeechatMode::Auto,
+            weechat_dir: String::new(),
+            weechat_args: vec![],
+            weechat_startup_commands: vec![],
+            weechat_env: vec![],
+            weechat_working_dir: String::new(),
+            weechat_log_file: get_base_state_dir()
+                .join("weechat-headless.log")
+                .display()
+                .to_string(),
+            weechat_log_max_size: 1024 * 1024,
+            slack_register_baseurl: "https://slack.com/oauth/authorize".to_string(),
+            slack_register_weeslack_client_id: "2468770254.51917335286".to_string(),
+            slack_register_scope: "client".to_string(),
+            slack_register_redirect_uri: "https%3A%2F%2Fwee-slack.github.io%2Fwee-slack%2Foauth"
+                .to_string(),
+            wee_slack_plugin_directory: String::new(),
+            wee_slack_plugin_filename: "wee_slack.py".to_string(),
+            sources: HashMap::new(),
+            file: None,
+            profile: None,
+            profiles: vec![],
+        }
+    }
+}
+
+impl Config {
+    pub fn source(&self, key: &str) -> ConfigSource {
+        self.sources
+            .get(key)
+            .cloned()
+            .unwrap_or(ConfigSource::Default)
+    }
+
+    /*
+     * The effective values, by key (secrets masked)
+     */
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let values = match serde_json::to_value(self) {
+            Ok(Value::Object(values)) => values,
+            _ => Map::new(),
         };
-        let config_filepath = format!("{config_dir}/{APP_NAME}.toml");
-
-        Config::builder()
-            .add_source(
-                config::File::with_name(&config_filepath)
-                    .format(config::FileFormat::Toml)
-                    .required(false),
-            )
-            .set_default(
-                CONFIG_FILE,
-                Path::new(&config_filepath)
-                    .exists()
-                    .then(|| config_filepath.clone()),
-            )
-            .unwrap()
-            .set_default(WEECHAT_PROGRAM_NAME, "weechat-headless")
-            .unwrap()
-            .set_default(WEECHAT_HOST, "127.0.0.1")
-            .unwrap()
-            .set_default(WEECHAT_RELAY_PORT, "8000")
-            .unwrap()
-            .set_default(WEECHAT_PASSWORD, "")
-            .unwrap()
-            .set_default(SLACK_REGISTER_BASEURL, "https://slack.com/oauth/authorize")
-            .unwrap()
-            .set_default(SLACK_REGISTER_WEESLACK_CLIENT_ID, "2468770254.51917335286")
-            .unwrap()
-            .set_default(SLACK_REGISTER_SCOPE, "client")
-            .unwrap()
-            .set_default(
-                SLACK_REGISTER_REDIRECT_URI,
-                "https%3A%2F%2Fwee-slack.github.io%2Fwee-slack%2Foauth",
-            )
-            .unwrap()
-            .set_default(
-                WEE_SLACK_PLUGIN_DIRECTORY,
-                "$HOME/.local/share/weechat/python".replace("$HOME", &home_dir),
-            )
-            .unwrap()
-            .set_default(WEE_SLACK_PLUGIN_FILENAME, "wee_slack.py")
-            .unwrap()
-            .build()
-            .unwrap()
+        CONFIG_KEYS
+            .iter()
+            .map(|(key, _)| {
+                let value = values.get(*key).cloned().unwrap_or(Value::Null);
+                let value = match value {
+                    // a fixed mask, the length of a secret is not shown either
+                    Value::String(secret) if SECRET_KEYS.contains(key) && !secret.is_empty() => {
+                        Value::String(SECRET_MASK.to_string())
+                    }
+                    value => value,
+                };
+                (*key, to_toml_value(&value))
+            })
+            .collect()
+    }
+
+    /*
+     * Checks the values themselves, once their types are known to be right
+     */
+    fn validate(&self) -> Vec<(&'static str, String)> {
+        let mut errors = vec![];
+        if self.weechat_host.trim().is_empty() {
+            errors.push(("sr_weechat_host", "must not be empty".to_string()));
+        }
+        if self.weechat_relay_port == 0 {
+            errors.push(("sr_weechat_relay_port", "must not be 0".to_string()));
+        }
+        let password_sources = [
+            ("sr_weechat_password", &self.weechat_password),
+            (
+                "sr_weechat_password_command",
+                &self.weechat_password_command,
+            ),
+            ("sr_weechat_password_file", &self.weechat_password_file),
+        ];
+        if password_sources
+            .iter()
+            .filter(|(_, v)| !v.is_empty())
+            .count()
+            > 1
+        {
+            for (key, value) in password_sources {
+                if !value.is_empty() {
+                    errors.push((key, "set only one password source".to_string()));
+                }
+            }
+        }
+        if self.weechat_program_name.trim().is_empty() {
+            errors.push(("sr_weechat_program_name", "must not be empty".to_string()));
+        }
+        for var in self.weechat_env.iter() {
+            if var.split_once('=').is_none_or(|(name, _)| name.is_empty()) {
+                errors.push((
+                    "sr_weechat_env",
+                    format!("expected NAME=value, found {var:?}"),
+                ));
+            }
+        }
+        if self.weechat_log_max_size == 0 {
+            errors.push(("sr_weechat_log_max_size", "must not be 0".to_string()));
+        }
+        if !self.slack_register_baseurl.starts_with("https://")
+            && !self.slack_register_baseurl.starts_with("http://")
+        {
+            errors.push((
+                "sr_slack_register_baseurl",
+                "expected an http(s) url".to_string(),
+            ));
+        }
+        if self.wee_slack_plugin_filename.trim().is_empty() {
+            errors.push((
+                "sr_wee_slack_plugin_filename",
+                "must not be empty".to_string(),
+            ));
+        }
+        errors
+    }
+}
+
+/*
+* The port used to be configured as a string, e.g "8000"
+*/
+fn deserialize_port<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Port {
+        Number(u64),
+        String(String),
+    }
+    let port = match Port::deserialize(deserializer)? {
+        Port::Number(port) => u16::try_from(port).ok(),
+        Port::String(port) => port.trim().parse::<u16>().ok(),
+    };
+    port.ok_or_else(|| serde::de::Error::custom("expected a port number (1-65535)"))
+}
+
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| match load_config() {
+        Ok(config) => config,
+        Err(errors) => {
+            eprintln!("Invalid configuration :");
+            for error in errors {
+                eprintln!("  {error}");
+            }
+            eprintln!("See '{APP_NAME} config validate'");
+            exit(1);
+        }
     })
 }
 
-pub fn get_config<'a, T: serde::Deserialize<'a>>(key: &str) -> T {
-    config().get::<T>(key).unwrap()
+/*
+* --config, else SLACKROPE_CONFIG, else slackrope.toml in the config directory
+*/
+pub fn get_config_path() -> PathBuf {
+    if let Some(path) = OVERRIDES.get().and_then(|o| o.config_file.clone()) {
+        return path;
+    }
+    if let Ok(path) = std::env::var(ENV_CONFIG_FILE) {
+        return PathBuf::from(expand_path(&path));
+    }
+    match std::env::var(XDG_CONFIG_HOME) {
+        Ok(dir) => Path::new(&dir).join(APP_NAME),
+        Err(_) => get_home_dir().join(".config").join(APP_NAME),
+    }
+    .join(format!("{APP_NAME}.toml"))
+}
+
+/*
+* Sets keys of slackrope.toml, in the selected profile's table if any,
+* keeping the rest of the file (comments, order) as it is
+*/
+pub fn write_config_values(values: &[(&str, toml_edit::Value)]) -> anyhow::Result<PathBuf> {
+    let path = get_config_path();
+    let content = match path.exists() {
+        true => {
+            std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?
+        }
+        false => String::new(),
+    };
+    let mut document = content
+        .parse::<toml_edit::DocumentMut>()
+        .with_context(|| format!("parsing {}", path.display()))?;
+    let table = match &config().profile {
+        Some(profile) => {
+            let mut profiles = toml_edit::Table::new();
+            profiles.set_implicit(true);
+            document
+                .entry(PROFILES_KEY)
+                .or_insert(toml_edit::Item::Table(profiles))
+                .as_table_mut()
+                .with_context(|| format!("{PROFILES_KEY} is not a table"))?
+                .entry(profile)
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .with_context(|| format!("{PROFILES_KEY}.{profile} is not a table"))?
+        }
+        None => document.as_table_mut(),
+    };
+    for (key, value) in values {
+        table[*key] = toml_edit::value(value.clone());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    std::fs::write(&path, document.to_string())
+        .with_context(|| format!("writing {}", path.display()))?;
+    Ok(path)
+}
+
+/*
+* Defaults, then the config file (when it exists), then SLACKROPE_* variables,
+* then command line flags.
+* Reports every unknown key, wrong type and bad value, not only the first one
+*/
+pub fn load_config() -> Result<Config, Vec<ConfigError>> {
+    load_config_internal(None)
+}
+
+/*
+* The configuration of a given profile, whichever profile is selected,
+* e.g for an aggregated hotlist (origin: what asks for it, for errors).
+* The --host/--port flags and their SLACKROPE_* variables would send every profile
+* to the same weechat: they are ignored
+*/
+pub fn load_profile_config(name: &str, origin: &str) -> Result<Config, Vec<ConfigError>> {
+    load_config_internal(Some((name, origin)))
+}
+
+fn load_config_internal(forced_profile: Option<(&str, &str)>) -> Result<Config, Vec<ConfigError>> {
+    let overrides = OVERRIDES.get().cloned().unwrap_or_default();
+    let path = get_config_path();
+    let is_explicit_path =
+        overrides.config_file.is_some() || std::env::var(ENV_CONFIG_FILE).is_ok();
+    let file_error = |line: Option<usize>, key: Option<&str>, message: String| ConfigError {
+        origin: path.display().to_string(),
+        line,
+        key: key.map(|k| k.to_string()),
+        message,
+    };
+
+    let defaults = match serde_json::to_value(Config::default()) {
+        Ok(Value::Object(values)) => values,
+        _ => Map::new(),
+    };
+    let is_ignored_override = |key: &str| forced_profile.is_some() && ENDPOINT_KEYS.contains(&key);
+    let mut values = defaults.clone();
+    let mut sources = HashMap::new();
+    let mut errors = vec![];
+
+    /* the config file: its top-level keys, then the selected [profiles.<name>] table */
+    let file = if path.exists() {
+        Some(path.clone())
+    } else if is_explicit_path {
+        return Err(vec![file_error(None, None, "file not found".to_string())]);
+    } else {
+        None
+    };
+    // the selected profile, and where it was selected
+    let mut profile = match (
+        forced_profile,
+        &overrides.profile,
+        std::env::var(ENV_PROFILE),
+    ) {
+        (Some((name, origin)), _, _) => Some((name.to_string(), origin.to_string(), None)),
+        (None, Some(name), _) => Some((name.clone(), "--profile".to_string(), None)),
+        (None, None, Ok(name)) => Some((name, ENV_PROFILE.to_string(), None)),
+        (None, None, Err(_)) => None,
+    };
+    let mut profiles = vec![];
+    if file.is_some() {
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| vec![file_error(None, None, format!("cannot read the file: {e}"))])?;
+        let document = toml_edit::ImDocument::parse(content.as_str()).map_err(|e| {
+            let line = e.span().map(|span| line_of(&content, span.start));
+            vec![file_error(line, None, e.message().trim().to_string())]
+        })?;
+        let root = document.as_table();
+        let mut table_errors = vec![];
+        let mut profile_tables = vec![];
+        if let Some(item) = root.get(DEFAULT_PROFILE_KEY) {
+            let line = line_of_key(root, DEFAULT_PROFILE_KEY, &content);
+            match item.as_str() {
+                Some(name) if profile.is_none() => {
+                    profile = Some((name.to_string(), path.display().to_string(), line))
+                }
+                Some(_) => {}
+                None => table_errors.push((
+                    line,
+                    DEFAULT_PROFILE_KEY.to_string(),
+                    "expected a profile name".to_string(),
+                )),
+            }
+        }
+        if let Some(item) = root.get(PROFILES_KEY) {
+            let line = line_of_key(root, PROFILES_KEY, &content);
+            match item.as_table() {
+                Some(table) => {
+                    for (name, item) in table.iter() {
+                        let line = line_of_key(table, name, &content);
+                        let key = format!("{PROFILES_KEY}.{name}");
+                        match item.as_table() {
+                            _ if !is_valid_profile_name(name) => table_errors.push((
+                                line,
+                                key,
+                                "expected a name made of letters, digits, '-' and '_'".to_string(),
+                            )),
+                            Some(profile_table) => profile_tables.push((name, profile_table)),
+                            None => table_errors.push((line, key, "expected a table".to_string())),
+                        }
+                    }
+                }
+                None => table_errors.push((
+                    line,
+                    PROFILES_KEY.to_string(),
+                    "expected [profiles.<name>] tables".to_string(),
+                )),
+            }
+        }
+
+        let lines = read_table_values(root, &content, "", &mut values, &mut table_errors);
+        for (key, line) in lines {
+            sources.insert(key, ConfigSource::File(line));
+        }
+        // every profile is checked, only the selected one applies
+        for (name, table) in profile_tables {
+            profiles.push(name.to_string());
+            let mut profile_values = values.clone();
+            let lines = read_table_values(
+                table,
+                &content,
+                &format!("{PROFILES_KEY}.{name}."),
+                &mut profile_values,
+                &mut table_errors,
+            );
+            if profile
+                .as_ref()
+                .is_some_and(|(selected, ..)| selected == name)
+            {
+                values = profile_values;
+                for (key, line) in lines {
+                    sources.insert(key, ConfigSource::Profile(name.to_string(), line));
+                }
+            }
+        }
+        errors.extend(
+            table_errors
+                .into_iter()
+                .map(|(line, key, message)| file_error(line, Some(&key), message)),
+        );
+    }
+    if let Some((name, origin, line)) = &profile {
+        if !profiles.contains(name) {
+            let message = match profiles.is_empty() {
+                true => format!("unknown profile {name:?}, there is no [profiles.<name>] table"),
+                false => format!(
+                    "unknown profile {name:?}, expected one of {}",
+                    profiles.join(", ")
+                ),
+            };
+            errors.push(ConfigError {
+                origin: origin.clone(),
+                line: *line,
+                key: None,
+                message,
+            });
+        }
+    }
+
+    /* SLACKROPE_* variables */
+    for (var, raw) in std::env::vars() {
+        let Some(name) = var.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        if var == ENV_CONFIG_FILE || var == ENV_PROFILE {
+            continue;
+        }
+        // e.g SLACKROPE_BIN set for another tool, see get_unknown_env_variables
+        let Some(key) = get_env_variable_key(name) else {
+            continue;
+        };
+        if is_ignored_override(key) {
+            continue;
+        }
+        // strings are taken as is, other values are written as in slackrope.toml
+        let value = match defaults.get(key) {
+            Some(Value::String(_)) => Value::String(raw),
+            _ => match raw.parse::<toml_edit::Value>() {
+                Ok(value) => to_json_value(&value),
+                Err(_) => Value::String(raw),
+            },
+        };
+        match apply_value(&mut values, key, value) {
+            Ok(()) => {
+                sources.insert(key.to_string(), ConfigSource::Env(var));
+            }
+            Err(message) => errors.push(ConfigError {
+                origin: var,
+                line: None,
+                key: Some(key.to_string()),
+                message,
+            }),
+        }
+    }
+
+    /* command line flags */
+    let flags = [
+        ("sr_weechat_host", "--host", overrides.host.map(Value::from)),
+        (
+            "sr_weechat_relay_port",
+            "--port",
+            overrides.port.map(Value::from),
+        ),
+        (
+            "sr_weechat_program_name",
+            "--program",
+            overrides.program.map(Value::from),
+        ),
+    ];
+    for (key, flag, value) in flags {
+        let Some(value) = value else {
+            continue;
+        };
+        if is_ignored_override(key) {
+            continue;
+        }
+        match apply_value(&mut values, key, value) {
+            Ok(()) => {
+                sources.insert(key.to_string(), ConfigSource::Flag(flag));
+            }
+            Err(message) => errors.push(ConfigError {
+                origin: flag.to_string(),
+                line: None,
+                key: Some(key.to_string()),
+                message,
+            }),
+        }
+    }
+
+    let mut config = serde_json::from_value::<Config>(Value::Object(values))
+        .map_err(|e| vec![file_error(None, None, e.to_string())])?;
+    for (key, message) in config.validate() {
+        let source = sources.get(key).cloned().unwrap_or(ConfigSource::Default);
+        let (origin, line) = match source {
+            ConfigSource::File(line) | ConfigSource::Profile(_, line) => {
+                (path.display().to_string(), Some(line))
+            }
+            ConfigSource::Env(var) => (var, None),
+            ConfigSource::Flag(flag) => (flag.to_string(), None),
+            ConfigSource::Default => ("default".to_string(), None),
+        };
+        errors.push(ConfigError {
+            origin,
+            line,
+            key: Some(key.to_string()),
+            message,
+        });
+    }
+    if !errors.is_empty() {
+        // file errors first, by line
+        errors.sort_by_key(|e| (e.line.is_none(), e.line));
+        return Err(errors);
+    }
+    // each profile runs its own weechat, which logs in its own state directory
+    let profile = profile.map(|(name, ..)| name);
+    if let Some(profile) = &profile {
+        if !sources.contains_key("sr_weechat_log_file") {
+            config.weechat_log_file = get_profile_state_dir(profile)
+                .join("weechat-headless.log")
+                .display()
+                .to_string();
+        }
+    }
+    config.sources = sources;
+    config.file = file;
+    config.profile = profile;
+    config.profiles = profiles;
+    Ok(config)
+}
+
+/*
+* Applies the sr_* keys of a table of the config file,
+* returns the line of each key applied
+*/
+fn read_table_values(
+    table: &toml_edit::Table,
+    content: &str,
+    prefix: &str,
+    values: &mut Map<String, Value>,
+    errors: &mut Vec<(Option<usize>, String, String)>,
+) -> Vec<(String, usize)> {
+    let mut lines = vec![];
+    for (key, item) in table.iter() {
+        if prefix.is_empty() && (key == DEFAULT_PROFILE_KEY || key == PROFILES_KEY) {
+            continue;
+        }
+        let line = line_of_key(table, key, content);
+        let name = format!("{prefix}{key}");
+        if !CONFIG_KEYS.iter().any(|(k, _)| *k == key) {
+            errors.push((line, name, "unknown key".to_string()));
+            continue;
+        }
+        let Some(value) = item.as_value() else {
+            errors.push((line, name, "expected a value".to_string()));
+            continue;
+        };
+        match apply_value(values, key, to_json_value(value)) {
+            Ok(()) => lines.push((key.to_string(), line.unwrap_or(0))),
+            Err(message) => errors.push((line, name, message)),
+        }
+    }
+    lines
+}
+
+fn line_of_key(table: &toml_edit::Table, key: &str, content: &str) -> Option<usize> {
+    table
+        .key(key)
+        .and_then(|k| k.span())
+        .map(|span| line_of(content, span.start))
+}
+
+/*
+* Profile names end up in paths and systemd unit names
+*/
+fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/*
+* Sets a value, unless it does not fit its key.
+* Each key is checked on its own, so that one error does not hide the others
+*/
+fn apply_value(values: &mut Map<String, Value>, key: &str, value: Value) -> Result<(), String> {
+    let mut candidate = values.clone();
+    candidate.insert(key.to_string(), value);
+    match serde_json::from_value::<Config>(Value::Object(candidate.clone())) {
+        Ok(_) => {
+            *values = candidate;
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+fn to_json_value(value: &toml_edit::Value) -> Value {
+    match value {
+        toml_edit::Value::String(s) => Value::String(s.value().clone()),
+        toml_edit::Value::Integer(i) => Value::from(*i.value()),
+        toml_edit::Value::Float(f) => Value::from(*f.value()),
+        toml_edit::Value::Boolean(b) => Value::Bool(*b.value()),
+        toml_edit::Value::Datetime(d) => Value::String(d.value().to_string()),
+        toml_edit::Value::Array(array) => Value::Array(array.iter().map(to_json_value).collect()),
+        toml_edit::Value::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(k, v)| (k.to_string(), to_json_value(v)))
+                .collect(),
+        ),
+    }
+}
+
+/*
+* The config key a SLACKROPE_* variable name (its prefix stripped) overrides
+*/
+fn get_env_variable_key(name: &str) -> Option<&'static str> {
+    let key = format!("sr_{}", name.to_lowercase());
+    CONFIG_KEYS.iter().find(|(k, _)| *k == key).map(|(k, _)| *k)
+}
+
+/*
+* The SLACKROPE_* variables which are not config keys, ignored by the config
+*/
+pub fn get_unknown_env_variables() -> Vec<String> {
+    let mut variables: Vec<String> = std::env::vars()
+        .map(|(var, _)| var)
+        .filter(|var| {
+            var.strip_prefix(ENV_PREFIX)
+                .is_some_and(|name| get_env_variable_key(name).is_none())
+                && var != ENV_CONFIG_FILE
+                && var != ENV_PROFILE
+        })
+        .collect();
+    variables.sort();
+    variables
+}
+
+/*
+* A value as written in slackrope.toml
+*/
+pub fn to_toml_value(value: &Value) -> String {
+    match value {
+        Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(to_toml_value)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        Value::String(s) => toml_edit::Value::from(s.as_str()).to_string(),
+        value => value.to_string(),
+    }
 }
 
 pub fn get_slack_register_url() -> std::string::String {
+    let config = config();
     format!(
         "{}?client_id={}&scope={}&redirect_uri={}",
-        get_config::<String>(SLACK_REGISTER_BASEURL),
-        get_config::<String>(SLACK_REGISTER_WEESLACK_CLIENT_ID),
-        get_config::<String>(SLACK_REGISTER_SCOPE),
-        get_config::<String>(SLACK_REGISTER_REDIRECT_URI)
+        config.slack_register_baseurl,
+        config.slack_register_weeslack_client_id,
+        config.slack_register_scope,
+        config.slack_register_redirect_uri
     )
 }
 
+/*
+* Remote mode: weechat runs on another machine, slackrope only talks to its relay.
+* "auto" turns it on when sr_weechat_host is not a loopback address
+*/
+pub fn is_remote_mode() -> bool {
+    let config = config();
+    match config.weechat_mode {
+        WeechatMode::Remote => true,
+        WeechatMode::Local => false,
+        WeechatMode::Auto => !is_loopback_host(&config.weechat_host),
+    }
+}
+
+fn is_loopback_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .trim_matches(['[', ']'])
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/*
+* Where slackrope keeps its own state (mutes, etc.),
+* a directory of its own for each profile
+*/
+pub fn get_state_dir() -> std::path::PathBuf {
+    match &config().profile {
+        Some(profile) => get_profile_state_dir(profile),
+        None => get_base_state_dir(),
+    }
+}
+
+pub fn get_profile_state_dir(profile: &str) -> std::path::PathBuf {
+    get_base_state_dir().join("profiles").join(profile)
+}
+
+fn get_base_state_dir() -> std::path::PathBuf {
+    match std::env::var(XDG_STATE_HOME) {
+        Ok(dir) => Path::new(&dir).join(APP_NAME),
+        Err(_) => get_home_dir().join(".local/state").join(APP_NAME),
+    }
+}
+
+/*
+* Expands a leading "~" or "$HOME" in a configured path
+*/
+pub fn expand_path(path: &str) -> String {
+    let home_dir = get_home_dir().display().to_string();
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{home_dir}/{rest}"),
+        None if path == "~" => home_dir,
+        None => path.replace("$HOME", &home_dir),
+    }
+}
+
+/*
+* Where systemd looks for user units
+*/
+pub fn get_systemd_user_dir() -> std::path::PathBuf {
+    match std::env::var(XDG_CONFIG_HOME) {
+        Ok(dir) => Path::new(&dir).join("systemd/user"),
+        Err(_) => get_home_dir().join(".config/systemd/user"),
+    }
+}
+
 fn get_home_dir() -> std::path::PathBuf {
     match home::home_dir() {
         Some(path) if !path.as_os_str().is_empty() => path,
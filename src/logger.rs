@@ -1,16 +1,20 @@
 pub use crate::cli::Cli;
-use log::{debug, info};
+use crate::utils::redact_tokens;
+use log::{debug, info, Record};
+use std::io::Write;
 
 pub fn set_logger(cli: &Cli) {
     // Note, only flags can have multiple occurrences
     match cli.debug {
         0 => {
             let _ = env_logger::builder()
+                .format(write_redacted_record)
                 .filter_level(log::LevelFilter::Warn)
                 .try_init();
         }
         1 => {
             let _ = env_logger::builder()
+                .format(write_redacted_record)
                 .filter_level(log::LevelFilter::Info)
                 .filter_module("skim", log::LevelFilter::Info)
                 .filter_module("tuikit", log::LevelFilter::Info)
@@ -23,6 +27,7 @@ pub fn set_logger(cli: &Cli) {
         }
         2 => {
             let _ = env_logger::builder()
+                .format(write_redacted_record)
                 .filter_level(log::LevelFilter::max())
                 .filter_module("skim", log::LevelFilter::Info)
                 .filter_module("tuikit", log::LevelFilter::Info)
@@ -36,3 +41,20 @@ pub fn set_logger(cli: &Cli) {
         _ => eprintln!("-d flag (debug-mode) was used more than twice."),
     }
 }
+
+/*
+* env_logger's default format, with slack tokens masked (e.g in the weechat commands logged)
+*/
+fn write_redacted_record(
+    buf: &mut env_logger::fmt::Formatter,
+    record: &Record,
+) -> std::io::Result<()> {
+    writeln!(
+        buf,
+        "[{} {} {}] {}",
+        buf.timestamp(),
+        record.level(),
+        record.target(),
+        redact_tokens(&record.args().to_string())
+    )
+}
@@ -4,47 +4,174 @@ use std::process::exit;
 use sysinfo::{System, SystemExt};
 
 mod cli;
+mod config;
 mod environment;
 mod logger;
 mod models;
+mod mutes;
+mod password;
+mod state;
 mod utils;
+mod weechat_buffers;
+mod weechat_conf;
 mod weechat_connection;
 mod weechat_health;
 mod weechat_hotlist;
+mod weechat_notify;
+mod weechat_plugin;
 mod weechat_process;
+mod weechat_relay;
+mod weechat_service;
 mod weechat_slack;
+mod weechat_tail;
+mod weechat_tokens;
+mod weechat_ui;
 
+use config::config_command;
+use mutes::{mute, print_mutes, unmute, MuteFlags};
 use weechat_health::print_weechat_health;
-use weechat_hotlist::{clear_hotlist, hotlist, HotlistFlags};
-use weechat_process::{kill_weechat_processes, WeechatSpawnFailed};
-use weechat_slack::{list_registered_slack_teams, print_register_url, register_slack_token};
+use weechat_hotlist::{clear_hotlist, hotlist, next_hotlist_buffer, HotlistFlags, NextFlags};
+use weechat_notify::{focus, set_buffer_notify, FocusFlags};
+use weechat_plugin::plugin_command;
+use weechat_process::{
+    adopt_weechat_process, kill_weechat_processes, print_weechat_status, restart_weechat,
+    start_weechat, stop_weechat, WeechatSpawnFailed,
+};
+use weechat_relay::{setup_relay, SetupRelayFlags};
+use weechat_service::service;
+use weechat_slack::{list_registered_slack_teams, register, RegisterFlags};
+use weechat_tail::{tail, TailFlags};
+use weechat_tokens::tokens_command;
+use weechat_ui::{ui, UiFlags};
 
 fn main() {
-    let mut system = System::new_all();
+    // empty: the commands which need it only inspect the weechat process
+    let mut system = System::new();
     let cli = cli::Cli::parse();
     logger::set_logger(&cli);
+    environment::set_config_overrides(environment::ConfigOverrides {
+        config_file: cli.config.clone(),
+        profile: cli.profile.clone(),
+        host: cli.host.clone(),
+        port: cli.port,
+        program: cli.program.clone(),
+    });
 
     match cli.command {
         cli::Commands::Hotlist {
             format,
             start,
             template,
+            aggregate,
         } => fold(hotlist(
-            &system,
+            &mut system,
             HotlistFlags {
                 format,
                 start,
                 template,
+                aggregate,
             },
         )),
-        cli::Commands::Clear => fold(clear_hotlist(&system)),
+        cli::Commands::Clear => fold(clear_hotlist(&mut system)),
+        cli::Commands::Start => fold(start_weechat(&mut system)),
+        cli::Commands::Stop { timeout } => fold(stop_weechat(&mut system, timeout)),
+        cli::Commands::Restart { timeout } => fold(restart_weechat(&mut system, timeout)),
+        cli::Commands::Status => fold(print_weechat_status(&mut system)),
         cli::Commands::Kill => fold(kill_weechat_processes(&mut system)),
+        cli::Commands::Adopt { pid, .. } => fold(adopt_weechat_process(&mut system, pid)),
+        cli::Commands::Service { action } => fold(service(&mut system, action)),
+        cli::Commands::Config { action, force } => fold(config_command(action, force)),
+        cli::Commands::Plugin {
+            action,
+            path,
+            force,
+        } => fold(plugin_command(&mut system, action, path, force)),
+        cli::Commands::SetupRelay {
+            tls,
+            unix,
+            bind,
+            offline,
+        } => fold(setup_relay(
+            &mut system,
+            SetupRelayFlags {
+                tls_port: tls,
+                unix_socket: unix,
+                bind_address: bind,
+                offline,
+            },
+        )),
         cli::Commands::ListTeams => fold(list_registered_slack_teams(&mut system)),
-        cli::Commands::Register { token } => match token {
-            Some(token) => fold(register_slack_token(&mut system, &token)),
-            None => fold(print_register_url()),
-        },
+        cli::Commands::Register {
+            token,
+            token_stdin,
+            token_file,
+        } => fold(register(
+            &mut system,
+            RegisterFlags {
+                token,
+                token_stdin,
+                token_file,
+            },
+        )),
+        cli::Commands::Tokens {
+            action,
+            team,
+            token,
+            token_stdin,
+            token_file,
+        } => fold(tokens_command(
+            &mut system,
+            action,
+            team,
+            RegisterFlags {
+                token,
+                token_stdin,
+                token_file,
+            },
+        )),
         cli::Commands::Health => fold(print_weechat_health(&mut system)),
+        cli::Commands::Next { show, mark_read } => fold(next_hotlist_buffer(
+            &mut system,
+            NextFlags { show, mark_read },
+        )),
+        cli::Commands::Mute {
+            target,
+            duration,
+            notify,
+        } => fold(mute(MuteFlags {
+            target,
+            duration,
+            notify,
+        })),
+        cli::Commands::Unmute { target } => fold(unmute(&target)),
+        cli::Commands::Mutes => fold(print_mutes()),
+        cli::Commands::Focus {
+            state,
+            duration,
+            allow,
+        } => fold(focus(FocusFlags {
+            state,
+            duration,
+            allow,
+        })),
+        cli::Commands::Notify { buffer, level } => fold(set_buffer_notify(&buffer, level)),
+        cli::Commands::Tail {
+            buffers,
+            workspace,
+            lines,
+            follow,
+            format,
+        } => fold(tail(
+            &mut system,
+            TailFlags {
+                buffers,
+                workspaces: workspace,
+                lines,
+                follow,
+                format,
+            },
+        )),
+        cli::Commands::Ui { lines } => fold(ui(&mut system, UiFlags { lines })),
     };
 
     info!("Exiting !");
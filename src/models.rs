@@ -19,6 +19,9 @@ pub struct SimpleHotlist {
     pub priority_1: i32,
     pub priority_2: i32,
     pub priority_3: i32,
+    /* aggregated hotlist: the endpoints which could not be reached */
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unreachable: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -26,6 +29,9 @@ pub struct DetailedHotlist {
     pub priority_1: Detailed,
     pub priority_2: Detailed,
     pub priority_3: Detailed,
+    /* aggregated hotlist: the endpoints which could not be reached */
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unreachable: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,9 +43,72 @@ pub struct Detailed {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Buffer {
     pub buffer: String,
+    /* aggregated hotlist: the endpoint (profile) the buffer comes from */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct SlackTeam {
     pub name: String,
 }
+
+#[derive(Serialize, Deserialize, Debug)]
+/*
+* The team a slack token connects, as slackrope learnt it
+* fingerprint: a truncated sha256 of the token, tokens themselves are never stored
+*/
+pub struct KnownToken {
+    pub team: String,
+    pub fingerprint: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Line {
+    pub buffer: String,
+    pub date: u64,
+    pub prefix: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+/*
+* target: a buffer (e.g "workspace.#channel") or a whole workspace (e.g "workspace")
+* until: unix timestamp, or none for an unlimited mute
+* notify: whether the mute was mirrored in weechat (buffer notify level)
+* buffers: the weechat buffers it set to notify none, with the level to restore
+*/
+pub struct Mute {
+    pub target: String,
+    pub until: Option<u64>,
+    pub notify: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buffers: Vec<FocusedBuffer>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+/*
+* A running focus session
+* buffers: the weechat buffers whose notify level was lowered,
+* with the level to restore
+*/
+pub struct Focus {
+    pub until: Option<u64>,
+    pub buffers: Vec<FocusedBuffer>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FocusedBuffer {
+    pub full_name: String,
+    pub notify: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+/*
+* The weechat instance slackrope owns (its pidfile)
+* cmd: the command line it was started with, to detect a reused pid
+*/
+pub struct WeechatInstance {
+    pub pid: u32,
+    pub cmd: Vec<String>,
+}
@@ -0,0 +1,217 @@
+pub use crate::models::{FocusedBuffer, Mute};
+pub use crate::state::{get_state_dir, lock_state, read_state, read_state_in, write_state};
+use crate::utils::{format_local_time, get_buffer_workspace, now};
+pub use crate::weechat_buffers::{get_buffers, send_buffer_input, BufferInfo};
+pub use crate::weechat_connection::init_connection;
+use crate::weechat_notify::restore_notify_levels;
+use anyhow::Result;
+use log::debug;
+use std::path::Path;
+use std::time::Duration;
+use weechat_relay_rs::Connection;
+
+const MUTES_FILE: &str = "mutes.json";
+/* held while mutes.json is read then written */
+const MUTES_LOCK_FILE: &str = "mutes.lock";
+
+pub struct MuteFlags {
+    pub target: String,
+    pub duration: Option<Duration>,
+    pub notify: bool,
+}
+
+pub fn mute(flags: MuteFlags) -> Result<()> {
+    let until = flags.duration.map(|d| now() + d.as_secs());
+    let mut connection = match flags.notify {
+        true => {
+            let mut connection = init_connection()?;
+            debug!("connection initiated");
+            sweep_expired_mutes(&mut connection)?;
+            Some(connection)
+        }
+        false => None,
+    };
+
+    let _lock = lock_state(MUTES_LOCK_FILE)?;
+    let mut mutes = read_mutes()?;
+    let previous = mutes.iter().position(|m| m.target == flags.target);
+    let previous = previous.map(|i| mutes.remove(i));
+    let mut muted: Vec<FocusedBuffer> = vec![];
+    if let Some(connection) = connection.as_mut() {
+        let buffers = get_target_buffers(connection, &flags.target)?;
+        if buffers.is_empty() {
+            println!(
+                "Error : could not find any buffer for {} in weechat",
+                flags.target
+            );
+            std::process::exit(1);
+        }
+        for buffer in buffers {
+            send_buffer_input(connection, &buffer.pointer, "/buffer notify none")?;
+            // muted again: the level to restore is the one before the first mute
+            let saved = previous
+                .iter()
+                .flat_map(|m| m.buffers.iter())
+                .find(|b| b.full_name == buffer.full_name);
+            muted.push(FocusedBuffer {
+                notify: saved.map_or(buffer.notify, |b| b.notify),
+                full_name: buffer.full_name,
+            });
+        }
+    } else if let Some(previous) = previous.filter(|m| m.notify) {
+        // not mirrored anymore, the previous mute's levels are restored
+        let mut connection = init_connection()?;
+        debug!("connection initiated");
+        restore_mute_notify(&mut connection, &previous)?;
+    }
+    println!("Muted {} {}", flags.target, format_until(until));
+    mutes.push(Mute {
+        target: flags.target,
+        until,
+        notify: flags.notify,
+        buffers: muted,
+    });
+    write_mutes(&mutes)
+}
+
+pub fn unmute(target: &str) -> Result<()> {
+    let _lock = lock_state(MUTES_LOCK_FILE)?;
+    let mut mutes = read_mutes()?;
+    let Some(index) = mutes.iter().position(|m| m.target == target) else {
+        println!("{target} is not muted");
+        return Ok(());
+    };
+    let removed = mutes.remove(index);
+    if removed.notify {
+        let mut connection = init_connection()?;
+        debug!("connection initiated");
+        restore_mute_notify(&mut connection, &removed)?;
+    }
+    println!("Unmuted {target}");
+    write_mutes(&mutes)
+}
+
+pub fn print_mutes() -> Result<()> {
+    if has_expired_notify_mutes() {
+        match init_connection() {
+            Ok(mut connection) => sweep_expired_mutes(&mut connection)?,
+            Err(e) => debug!("{:?}", e),
+        }
+    }
+    let mutes = get_active_mutes();
+    if mutes.is_empty() {
+        println!("No muted buffer or workspace");
+    }
+    for mute in mutes {
+        println!("  - {} {}", mute.target, format_until(mute.until));
+    }
+    Ok(())
+}
+
+/*
+* The mutes the hotlist should honor, expired ones excluded
+*/
+pub fn get_active_mutes() -> Vec<Mute> {
+    get_active_mutes_in(&get_state_dir())
+}
+
+/*
+* The active mutes of another state directory, e.g another profile's
+*/
+pub fn get_active_mutes_in(state_dir: &Path) -> Vec<Mute> {
+    match read_state_in::<Vec<Mute>>(state_dir, MUTES_FILE).map(Option::unwrap_or_default) {
+        Ok(mutes) => {
+            let now = now();
+            mutes
+                .into_iter()
+                .filter(|m| m.until.is_none_or(|until| until > now))
+                .collect()
+        }
+        Err(e) => {
+            debug!("{:?}", e);
+            vec![]
+        }
+    }
+}
+
+/*
+* `/buffer notify none` is saved by weechat (it survives a restart),
+* so slackrope restores the notify level of the expired mutes itself
+*/
+pub fn sweep_expired_mutes(connection: &mut Connection) -> Result<()> {
+    if !has_expired_notify_mutes() {
+        return Ok(());
+    }
+    let _lock = lock_state(MUTES_LOCK_FILE)?;
+    let mutes = read_mutes()?;
+    let now = now();
+    for mute in mutes.iter() {
+        if mute.notify && mute.until.is_some_and(|until| until <= now) {
+            restore_mute_notify(connection, mute)?;
+            debug!("expired mute of {} restored", mute.target);
+        }
+    }
+    write_mutes(&mutes)
+}
+
+/*
+* A mute saved before slackrope kept the levels has none to restore: reset them
+*/
+fn restore_mute_notify(connection: &mut Connection, mute: &Mute) -> Result<()> {
+    if !mute.buffers.is_empty() {
+        return restore_notify_levels(connection, &mute.buffers);
+    }
+    for buffer in get_target_buffers(connection, &mute.target)? {
+        send_buffer_input(connection, &buffer.pointer, "/buffer notify reset")?;
+    }
+    Ok(())
+}
+
+fn has_expired_notify_mutes() -> bool {
+    let now = now();
+    read_mutes().is_ok_and(|mutes| {
+        mutes
+            .iter()
+            .any(|m| m.notify && m.until.is_some_and(|until| until <= now))
+    })
+}
+
+pub fn is_muted(mutes: &[Mute], buffer_name: &str) -> bool {
+    mutes.iter().any(|m| {
+        m.target == buffer_name
+            || m.target == get_buffer_workspace(buffer_name)
+            || m.target.strip_prefix("python.") == Some(buffer_name)
+    })
+}
+
+fn get_target_buffers(connection: &mut Connection, target: &str) -> Result<Vec<BufferInfo>> {
+    Ok(get_buffers(connection, Some("python.*"))?
+        .into_iter()
+        .filter(|b| {
+            b.matches(target) || (!b.is_slack_team() && get_buffer_workspace(&b.name) == target)
+        })
+        .collect())
+}
+
+fn format_until(until: Option<u64>) -> String {
+    match until {
+        Some(until) => format!("until {}", format_local_time(until, true)),
+        None => "until unmuted".to_string(),
+    }
+}
+
+/*
+* Expired mutes are kept until the next write (or sweep)
+*/
+fn read_mutes() -> Result<Vec<Mute>> {
+    Ok(read_state(MUTES_FILE)?.unwrap_or_default())
+}
+
+fn write_mutes(mutes: &[Mute]) -> Result<()> {
+    let now = now();
+    let mutes: Vec<&Mute> = mutes
+        .iter()
+        .filter(|m| m.until.is_none_or(|until| until > now))
+        .collect();
+    write_state(MUTES_FILE, &mutes)
+}
@@ -0,0 +1,134 @@
+use crate::environment::{config, expand_path, Config};
+use crate::weechat_conf::{get_weechat_conf_path, WeechatConf};
+use anyhow::{anyhow, bail, Context, Result};
+use log::debug;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command, Stdio};
+use std::sync::OnceLock;
+
+/*
+* The relay password, from the first configured source:
+* sr_weechat_password_command, sr_weechat_password_file, or sr_weechat_password
+* (which may refer to weechat's secured data, e.g "${sec.data.relay}")
+*/
+pub fn get_weechat_password() -> &'static str {
+    static PASSWORD: OnceLock<String> = OnceLock::new();
+    PASSWORD.get_or_init(|| match read_weechat_password(config()) {
+        Ok(password) => password,
+        Err(error) => {
+            println!("Error : cannot get the weechat relay password: {error:#}");
+            exit(1);
+        }
+    })
+}
+
+pub fn get_weechat_password_source() -> &'static str {
+    let config = config();
+    if !config.weechat_password_command.is_empty() {
+        "sr_weechat_password_command"
+    } else if !config.weechat_password_file.is_empty() {
+        "sr_weechat_password_file"
+    } else if get_sec_data_name(&config.weechat_password).is_some() {
+        "weechat sec.conf"
+    } else if !config.weechat_password.is_empty() {
+        "sr_weechat_password"
+    } else {
+        "none"
+    }
+}
+
+/*
+* The config file, when it holds the password and other users can read it
+*/
+pub fn get_exposed_password_config_file() -> Option<PathBuf> {
+    let config = config();
+    let holds_password = config.source("sr_weechat_password").is_file()
+        && !config.weechat_password.is_empty()
+        && get_sec_data_name(&config.weechat_password).is_none();
+    let path = config.file.as_ref()?;
+    let mode = fs::metadata(path).ok()?.permissions().mode();
+    (holds_password && mode & 0o044 != 0).then(|| path.clone())
+}
+
+/*
+* The relay password of a given configuration, e.g another profile's
+*/
+pub fn read_weechat_password(config: &Config) -> Result<String> {
+    if !config.weechat_password_command.is_empty() {
+        return run_password_command(&config.weechat_password_command);
+    }
+    if !config.weechat_password_file.is_empty() {
+        return read_password_file(Path::new(&expand_path(&config.weechat_password_file)));
+    }
+    match get_sec_data_name(&config.weechat_password) {
+        Some(name) => read_sec_data(config, name)
+            .map_err(|e| anyhow!("{e:#}, use sr_weechat_password_command instead")),
+        None => Ok(config.weechat_password.clone()),
+    }
+}
+
+/*
+* The first line printed by the command, like `pass show` does
+*/
+fn run_password_command(command: &str) -> Result<String> {
+    debug!("...running sr_weechat_password_command");
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("running {command:?}"))?;
+    if !output.status.success() {
+        bail!("{command:?} failed ({})", output.status);
+    }
+    let stdout = String::from_utf8(output.stdout).context("reading the command output")?;
+    Ok(stdout.lines().next().unwrap_or_default().to_string())
+}
+
+/*
+* Like ssh keys, the file is refused when other users can access it
+*/
+fn read_password_file(path: &Path) -> Result<String> {
+    let content = read_private_file(path)?;
+    Ok(content.lines().next().unwrap_or_default().to_string())
+}
+
+/*
+* A file holding a secret (a password, a slack token), refused when other users can access it
+*/
+pub fn read_private_file(path: &Path) -> Result<String> {
+    let metadata = fs::metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        bail!(
+            "{} is accessible by other users, run 'chmod 600 {}'",
+            path.display(),
+            path.display()
+        );
+    }
+    fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))
+}
+
+pub fn get_sec_data_name(password: &str) -> Option<&str> {
+    password
+        .trim()
+        .strip_prefix("${sec.data.")
+        .and_then(|name| name.strip_suffix('}'))
+}
+
+/*
+* A value of the [data] section of weechat's sec.conf,
+* only readable when weechat stores it unencrypted (no passphrase)
+*/
+pub fn read_sec_data(config: &Config, name: &str) -> Result<String> {
+    let sec = WeechatConf::read(&get_weechat_conf_path(config, "sec.conf"))?;
+    if !sec.exists() {
+        bail!("{} not found", sec.path.display());
+    }
+    if sec.get("data", "__passphrase__").as_deref() == Some("on") {
+        bail!("{} is encrypted with a passphrase", sec.path.display());
+    }
+    sec.get("data", name)
+        .ok_or_else(|| anyhow!("no sec.data.{name} in {}", sec.path.display()))
+}
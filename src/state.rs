@@ -0,0 +1,71 @@
+pub use crate::environment::get_state_dir;
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/*
+* slackrope's own state files (mutes, focus, etc.), stored as json
+* in the state directory
+*/
+pub fn read_state<T: DeserializeOwned>(filename: &str) -> Result<Option<T>> {
+    read_state_in(&get_state_dir(), filename)
+}
+
+/*
+* A state file of another state directory, e.g another profile's
+*/
+pub fn read_state_in<T: DeserializeOwned>(dir: &Path, filename: &str) -> Result<Option<T>> {
+    let path = dir.join(filename);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+    let state =
+        serde_json::from_str(&content).with_context(|| format!("parsing {}", path.display()))?;
+    Ok(Some(state))
+}
+
+pub fn write_state<T: Serialize>(filename: &str, state: &T) -> Result<()> {
+    let dir = get_state_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = dir.join(filename);
+    std::fs::write(&path, serde_json::to_string_pretty(state)?)
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+pub fn remove_state(filename: &str) -> Result<()> {
+    let path = get_state_dir().join(filename);
+    if path.exists() {
+        std::fs::remove_file(&path).with_context(|| format!("removing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/*
+* An exclusive lock on a state file, shared between slackrope processes,
+* released when dropped
+*/
+pub struct StateLock {
+    _file: std::fs::File,
+}
+
+pub fn lock_state(filename: &str) -> Result<StateLock> {
+    let dir = get_state_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("creating {}", dir.display()))?;
+    let path = dir.join(filename);
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    // blocks until the other slackrope process releases it
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("locking {}", path.display()));
+    }
+    Ok(StateLock { _file: file })
+}
@@ -1,8 +1,14 @@
-use std::time::Duration;
+use std::time::{SystemTime, UNIX_EPOCH};
 use weechat_relay_rs::messages::WString;
 
-pub fn sleep(seconds: u64) {
-    std::thread::sleep(Duration::from_secs(seconds))
+/*
+* Current unix timestamp, in seconds
+*/
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 pub fn to_utf8_lossy(ws: &WString) -> Option<std::borrow::Cow<'_, str>> {
@@ -26,3 +32,129 @@ pub fn match_string(ws: &WString, search_term: &str) -> bool {
     };
     false
 }
+
+/*
+* Removes weechat color codes from a string
+* @see https://weechat.org/files/doc/stable/weechat_relay_protocol.en.html#colors
+*/
+pub fn strip_weechat_colors(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            // reset
+            '\x1C' => (),
+            // set or remove attribute
+            '\x1A' | '\x1B' => {
+                chars.next();
+            }
+            // color
+            '\x19' => match chars.peek() {
+                Some('F') | Some('B') => {
+                    chars.next();
+                    skip_color(&mut chars);
+                }
+                Some('*') => {
+                    chars.next();
+                    skip_color(&mut chars);
+                    if let Some(',') | Some('~') = chars.peek() {
+                        chars.next();
+                        skip_color(&mut chars);
+                    }
+                }
+                Some('b') => {
+                    chars.next();
+                    chars.next();
+                }
+                Some('E') | Some('\x1C') => {
+                    chars.next();
+                }
+                _ => skip_color(&mut chars),
+            },
+            c => output.push(c),
+        }
+    }
+    output
+}
+
+fn skip_color(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    // optional attributes
+    while let Some('*') | Some('!') | Some('/') | Some('_') | Some('|') = chars.peek() {
+        chars.next();
+    }
+    // either "@" + 5 digits (extended color), or 2 digits (weechat color)
+    let digits = match chars.peek() {
+        Some('@') => {
+            chars.next();
+            5
+        }
+        _ => 2,
+    };
+    for _ in 0..digits {
+        if chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+            chars.next();
+        }
+    }
+}
+
+/*
+* Formats a unix timestamp in the local timezone, e.g "14:02" or "2025-02-08 14:02"
+*/
+pub fn format_local_time(timestamp: u64, with_date: bool) -> String {
+    let time = timestamp as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return timestamp.to_string();
+    }
+    if with_date {
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min
+        )
+    } else {
+        format!("{:02}:{:02}", tm.tm_hour, tm.tm_min)
+    }
+}
+
+/*
+* The slack workspace a wee-slack buffer belongs to.
+* e.g "slack.workspace" (team buffer) or "workspace.#channel" => "workspace"
+*/
+pub fn get_buffer_workspace(buffer_name: &str) -> &str {
+    let mut split = buffer_name.split('.');
+    match (split.next(), split.next()) {
+        (Some("slack"), Some(workspace)) => workspace,
+        (Some(workspace), _) => workspace,
+        _ => buffer_name,
+    }
+}
+
+/*
+* Slack tokens and session cookies (xoxp-..., xoxc-..., xoxd-...) masked,
+* so that they never reach a log or an error message
+*/
+pub fn redact_tokens(text: &str) -> String {
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find("xox") {
+        let after = &rest.as_bytes()[i + 3..];
+        let is_token = after.len() > 2 && after[0].is_ascii_alphabetic() && after[1] == b'-';
+        if !is_token {
+            redacted.push_str(&rest[..i + 3]);
+            rest = &rest[i + 3..];
+            continue;
+        }
+        redacted.push_str(&rest[..i + 5]);
+        redacted.push_str("****");
+        let token_len = rest[i + 5..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "-%._+/=".contains(c)))
+            .unwrap_or(rest.len() - i - 5);
+        rest = &rest[i + 5 + token_len..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
@@ -0,0 +1,331 @@
+pub use crate::utils::{clean_string, now, strip_weechat_colors};
+use anyhow::Result;
+use log::{debug, info};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use weechat_relay_rs::commands::{
+    Command, Count, Countable, HdataCommand, InfolistCommand, InputCommand, PointerOrName,
+    StrArgument, StringArgument,
+};
+use weechat_relay_rs::messages::{GenericHdata, Object, ObjectRef, Pointer, WInfolist};
+use weechat_relay_rs::Connection;
+
+/*
+* A line of a weechat buffer, as returned by the `line_data` hdata
+* (either requested with `hdata`, or pushed by `sync` in a `_buffer_line_added` event).
+* Prefix and message are stripped of weechat color codes.
+*/
+#[derive(Debug, Clone)]
+pub struct BufferLine {
+    pub buffer_pointer: Option<Pointer>,
+    pub date: u64,
+    pub prefix: String,
+    pub message: String,
+}
+
+/*
+* A weechat buffer, as returned by the `buffer` infolist
+* e.g full_name: "python.workspace.#channel", name: "workspace.#channel"
+*/
+#[derive(Debug, Clone)]
+pub struct BufferInfo {
+    pub pointer: Pointer,
+    pub full_name: String,
+    pub name: String,
+    pub notify: i32,
+    pub local_variables: HashMap<String, String>,
+}
+
+impl BufferInfo {
+    pub fn matches(&self, name: &str) -> bool {
+        self.full_name == name || self.name == name
+    }
+
+    /* wee-slack sets the "slack_type" local variable of its team (server) buffers to "team" */
+    pub fn is_slack_team(&self) -> bool {
+        self.local_variables.get("slack_type").map(String::as_str) == Some("team")
+    }
+
+    /* its channel, dm and thread buffers carry another "slack_type" (e.g "channel", "im") */
+    pub fn is_slack_channel(&self) -> bool {
+        self.local_variables.contains_key("slack_type") && !self.is_slack_team()
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.local_variables.get("type").map(String::as_str) == Some("private")
+    }
+}
+
+/*
+* pattern: a buffer full name, wildcard "*" allowed (e.g "python.*")
+*/
+pub fn get_buffers(connection: &mut Connection, pattern: Option<&str>) -> Result<Vec<BufferInfo>> {
+    let info_command = InfolistCommand::new(
+        StrArgument::new("buffer").unwrap().to_stringargument(),
+        None,
+        match pattern {
+            Some(pattern) => vec![StringArgument::new(pattern.to_string())
+                .map_err(|_| anyhow::anyhow!("Buffer pattern must fit on a single line"))?],
+            None => vec![],
+        },
+    );
+    connection.send_command(&Command {
+        id: None,
+        command: info_command,
+    })?;
+    debug!("buffer infolist request sent");
+
+    let message = connection.get_message();
+    match message {
+        Ok(m) => match m.objects.first() {
+            Some(Object::Inl(infolist)) => Ok(build_buffers(infolist)),
+            Some(_) => {
+                info!("Could not parse the buffer infolist response");
+                Ok(vec![])
+            }
+            None => Ok(vec![]),
+        },
+        Err(e) => {
+            debug!("{:?}", e);
+            info!("Could not receive the buffer infolist response");
+            Ok(vec![])
+        }
+    }
+}
+
+fn build_buffers(infolist: &WInfolist) -> Vec<BufferInfo> {
+    let mut buffers = vec![];
+    for item in infolist.items.iter() {
+        let mut pointer = None;
+        let mut full_name = String::new();
+        let mut name = String::new();
+        let mut notify = 0;
+        let mut localvar_names = HashMap::new();
+        let mut localvar_values = HashMap::new();
+        for variable in item.variables.iter() {
+            let variable_name = clean_string(&variable.name);
+            match (variable_name.as_str(), &variable.value) {
+                ("pointer", Object::Ptr(p)) => pointer = Some(p.clone()),
+                ("full_name", Object::Str(s)) => full_name = clean_string(s),
+                ("name", Object::Str(s)) => name = clean_string(s),
+                ("notify", Object::Int(i)) => notify = *i,
+                (v, Object::Str(s)) if v.starts_with("localvar_name_") => {
+                    localvar_names.insert(v["localvar_name_".len()..].to_string(), clean_string(s));
+                }
+                (v, Object::Str(s)) if v.starts_with("localvar_value_") => {
+                    localvar_values
+                        .insert(v["localvar_value_".len()..].to_string(), clean_string(s));
+                }
+                _ => (),
+            }
+        }
+        if let Some(pointer) = pointer {
+            let local_variables = localvar_names
+                .into_iter()
+                .filter_map(|(index, name)| localvar_values.remove(&index).map(|v| (name, v)))
+                .collect();
+            buffers.push(BufferInfo {
+                pointer,
+                full_name,
+                name,
+                notify,
+                local_variables,
+            })
+        }
+    }
+    buffers
+}
+
+pub fn build_buffer_lines_command(
+    id: Option<&str>,
+    buffer_pointer: &Pointer,
+    count: usize,
+) -> Command<HdataCommand> {
+    let hdata_command = HdataCommand::new(
+        StrArgument::new("buffer").unwrap().to_stringargument(),
+        Countable::new(None, PointerOrName::Pointer(buffer_pointer.clone())),
+        vec![
+            Countable::new(
+                None,
+                StrArgument::new("own_lines").unwrap().to_stringargument(),
+            ),
+            Countable::new(
+                Some(Count::Count(-(count as i32))),
+                StrArgument::new("last_line").unwrap().to_stringargument(),
+            ),
+            Countable::new(None, StrArgument::new("data").unwrap().to_stringargument()),
+        ],
+        vec![
+            StrArgument::new("buffer").unwrap().to_stringargument(),
+            StrArgument::new("date").unwrap().to_stringargument(),
+            StrArgument::new("prefix").unwrap().to_stringargument(),
+            StrArgument::new("message").unwrap().to_stringargument(),
+        ],
+    );
+    Command {
+        id: id.map(|id| StrArgument::new(id).unwrap().to_stringargument()),
+        command: hdata_command,
+    }
+}
+
+pub fn send_buffer_lines_request(
+    connection: &mut Connection,
+    buffer_pointer: &Pointer,
+    count: usize,
+) -> Result<()> {
+    connection.send_command(&build_buffer_lines_command(None, buffer_pointer, count))?;
+    Ok(())
+}
+
+pub fn get_buffer_lines_response(
+    connection: &mut Connection,
+    count: usize,
+) -> Result<Vec<BufferLine>> {
+    let message = connection.get_message();
+    match message {
+        Ok(m) => match m.objects.first() {
+            Some(Object::Hda(hdata)) => Ok(build_buffer_lines(hdata, count)),
+            Some(_) => {
+                info!("Could not parse the buffer lines response");
+                Ok(vec![])
+            }
+            None => Ok(vec![]),
+        },
+        Err(e) => {
+            debug!("{:?}", e);
+            info!("Could not receive the buffer lines response");
+            Ok(vec![])
+        }
+    }
+}
+
+/*
+* Lines requested from `last_line` come newest first:
+* we return (at most) the `count` newest lines, oldest first.
+*/
+pub fn build_buffer_lines(hdata: &GenericHdata, count: usize) -> Vec<BufferLine> {
+    let mut lines = build_line_data(hdata);
+    lines.reverse();
+    if lines.len() > count {
+        lines.drain(..lines.len() - count);
+    }
+    lines
+}
+
+/*
+* Builds lines from a `line_data` hdata, in the order they were sent.
+*/
+pub fn build_line_data(hdata: &GenericHdata) -> Vec<BufferLine> {
+    if hdata.set_values.is_empty() {
+        return vec![];
+    }
+    hdata
+        .sets()
+        .iter()
+        .map(|set| {
+            let mut line = BufferLine {
+                buffer_pointer: None,
+                date: 0,
+                prefix: String::new(),
+                message: String::new(),
+            };
+            for (key, value) in set.iter() {
+                match (key.as_slice(), value) {
+                    (b"buffer", ObjectRef::Ptr(pointer)) => {
+                        line.buffer_pointer = Some((*pointer).clone())
+                    }
+                    (b"date", ObjectRef::Tim(date)) => line.date = **date,
+                    (b"prefix", ObjectRef::Str(prefix)) => {
+                        line.prefix = strip_weechat_colors(&clean_string(prefix))
+                    }
+                    (b"message", ObjectRef::Str(message)) => {
+                        line.message = strip_weechat_colors(&clean_string(message))
+                    }
+                    _ => (),
+                }
+            }
+            line
+        })
+        .collect()
+}
+
+pub fn build_buffer_input_command(
+    id: Option<&str>,
+    buffer_pointer: &Pointer,
+    data: &str,
+) -> Result<Command<InputCommand>> {
+    let input_command = InputCommand::new(
+        PointerOrName::Pointer(buffer_pointer.clone()),
+        StringArgument::new(data.to_string())
+            .map_err(|_| anyhow::anyhow!("Input must fit on a single line"))?,
+    );
+    Ok(Command {
+        id: id.map(|id| StrArgument::new(id).unwrap().to_stringargument()),
+        command: input_command,
+    })
+}
+
+/*
+* Sends some text (or a command) to a buffer,
+* exactly as if it had been typed in the buffer input bar.
+*/
+pub fn send_buffer_input(
+    connection: &mut Connection,
+    buffer_pointer: &Pointer,
+    data: &str,
+) -> Result<()> {
+    connection.send_command(&build_buffer_input_command(None, buffer_pointer, data)?)?;
+    debug!("successfully sent input to buffer {buffer_pointer}");
+    Ok(())
+}
+
+/*
+* Asks weechat for a value the relay cannot read, through a core buffer local variable:
+* build_command gets a nonce and returns a command writing "<nonce> <answer>" to it
+* (e.g with /eval or /python eval). The nonce tells the answer apart from a previous one,
+* the variable is deleted once read. None when no answer came before the timeout.
+*/
+pub fn ask_core_buffer(
+    connection: &mut Connection,
+    localvar: &str,
+    build_command: impl FnOnce(&str) -> String,
+    timeout: Duration,
+) -> Result<Option<String>> {
+    let Some(core) = get_buffers(connection, Some("core.weechat"))?
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+    let nonce = format!("{}-{}", now(), std::process::id());
+    send_buffer_input(connection, &core.pointer, &build_command(&nonce))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let answer = get_buffers(connection, Some("core.weechat"))?
+            .into_iter()
+            .find_map(|b| b.local_variables.get(localvar).cloned());
+        if let Some(answer) = answer.as_deref().and_then(|a| a.strip_prefix(&nonce)) {
+            send_buffer_input(
+                connection,
+                &core.pointer,
+                &format!("/buffer set localvar_del_{localvar}"),
+            )?;
+            return Ok(Some(answer.trim_start().to_string()));
+        }
+        if Instant::now() > deadline {
+            debug!("no answer in the {localvar} local variable");
+            return Ok(None);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+}
+
+/*
+* Removes the buffer from the hotlist, and sets its read marker.
+*/
+pub fn send_mark_buffer_read(connection: &mut Connection, buffer_pointer: &Pointer) -> Result<()> {
+    send_buffer_input(connection, buffer_pointer, "/buffer set hotlist -1")?;
+    send_buffer_input(connection, buffer_pointer, "/buffer set unread")?;
+    debug!("successfully marked buffer {buffer_pointer} as read");
+    Ok(())
+}
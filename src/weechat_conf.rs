@@ -0,0 +1,364 @@
+use crate::environment::{config, expand_path, is_remote_mode, Config, XDG_CONFIG_HOME};
+use crate::weechat_connection::{get_weechat_info, init_connection};
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+pub const XDG_DATA_HOME: &str = "XDG_DATA_HOME";
+pub const XDG_CACHE_HOME: &str = "XDG_CACHE_HOME";
+/* weechat's equivalent of --dir */
+pub const WEECHAT_HOME: &str = "WEECHAT_HOME";
+
+/*
+* The directories weechat reads and writes
+* @see https://weechat.org/files/doc/stable/weechat_user.en.html#files_and_directories
+*/
+#[derive(Debug, Clone)]
+pub struct WeechatDirs {
+    /* the .conf files */
+    pub config: PathBuf,
+    /* scripts, logs */
+    pub data: PathBuf,
+    pub cache: PathBuf,
+}
+
+/*
+* The directories of the running weechat, asked over its relay
+* (a build or a package may use other defaults), else get_weechat_dirs
+*/
+pub fn detect_weechat_dirs() -> WeechatDirs {
+    let mut dirs = get_weechat_dirs(config());
+    if is_remote_mode() {
+        return dirs;
+    }
+    let mut connection = match init_connection() {
+        Ok(connection) => connection,
+        Err(e) => {
+            debug!("{:?}", e);
+            return dirs;
+        }
+    };
+    for (name, dir) in [
+        ("weechat_config_dir", &mut dirs.config),
+        ("weechat_data_dir", &mut dirs.data),
+        // weechat >= 3.2
+        ("weechat_cache_dir", &mut dirs.cache),
+    ] {
+        match get_weechat_info(&mut connection, name) {
+            Ok(Some(value)) => *dir = PathBuf::from(value),
+            Ok(None) => {}
+            Err(e) => {
+                debug!("{:?}", e);
+                break;
+            }
+        }
+    }
+    dirs
+}
+
+/*
+* sr_weechat_dir (--dir) or WEECHAT_HOME when set, either one directory or "config:data:cache[:runtime]",
+* else ~/.weechat when only it exists (weechat < 3.2), else the XDG directories
+*/
+pub fn get_weechat_dirs(config: &Config) -> WeechatDirs {
+    let weechat_home = config
+        .weechat_env
+        .iter()
+        .find_map(|var| {
+            var.strip_prefix(&format!("{WEECHAT_HOME}="))
+                .map(String::from)
+        })
+        .or_else(|| std::env::var(WEECHAT_HOME).ok())
+        .unwrap_or_default();
+    let weechat_dir = match config.weechat_dir.is_empty() {
+        true => &weechat_home,
+        false => &config.weechat_dir,
+    };
+    if !weechat_dir.is_empty() {
+        let dirs: Vec<PathBuf> = weechat_dir
+            .split(':')
+            .map(|dir| PathBuf::from(expand_path(dir)))
+            .collect();
+        let dir = |i: usize| dirs.get(i).unwrap_or(&dirs[0]).clone();
+        return WeechatDirs {
+            config: dir(0),
+            data: dir(1),
+            cache: dir(2),
+        };
+    }
+    let xdg_dir = |var: &str, default: &str| match std::env::var(var) {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir).join("weechat"),
+        _ => PathBuf::from(expand_path(default)),
+    };
+    let config_dir = xdg_dir(XDG_CONFIG_HOME, "~/.config/weechat");
+    let legacy_dir = PathBuf::from(expand_path("~/.weechat"));
+    if !config_dir.exists() && legacy_dir.exists() {
+        return WeechatDirs {
+            config: legacy_dir.clone(),
+            data: legacy_dir.clone(),
+            cache: legacy_dir,
+        };
+    }
+    WeechatDirs {
+        config: config_dir,
+        data: xdg_dir(XDG_DATA_HOME, "~/.local/share/weechat"),
+        cache: xdg_dir(XDG_CACHE_HOME, "~/.cache/weechat"),
+    }
+}
+
+/*
+* e.g get_weechat_conf_path(config, "relay.conf")
+*/
+pub fn get_weechat_conf_path(config: &Config, filename: &str) -> PathBuf {
+    get_weechat_dirs(config).config.join(filename)
+}
+
+/*
+* sr_wee_slack_plugin_directory, else the python directory of weechat's data directory
+*/
+pub fn get_wee_slack_plugin_directory(dirs: &WeechatDirs) -> PathBuf {
+    match config().wee_slack_plugin_directory.as_str() {
+        "" => dirs.data.join("python"),
+        directory => PathBuf::from(expand_path(directory)),
+    }
+}
+
+/*
+* Stores a value in the [data] section of sec.conf (what /secure set does),
+* only possible when weechat stores it unencrypted (no passphrase)
+*/
+pub fn set_sec_data(config: &Config, name: &str, value: &str) -> Result<PathBuf> {
+    let mut sec = WeechatConf::read(&get_weechat_conf_path(config, "sec.conf"))?;
+    match sec.get("data", "__passphrase__").as_deref() {
+        Some("on") => bail!("{} is encrypted with a passphrase", sec.path.display()),
+        Some(_) => {}
+        None => sec.set("data", "__passphrase__", &ConfValue::Raw("off".to_string())),
+    }
+    sec.set("data", name, &ConfValue::String(value.to_string()));
+    sec.save()?;
+    Ok(sec.path)
+}
+
+/*
+* A value to write: weechat quotes strings, not integers nor booleans
+*/
+pub enum ConfValue {
+    String(String),
+    Raw(String),
+}
+
+impl ConfValue {
+    fn to_conf_string(&self) -> String {
+        match self {
+            ConfValue::String(value) => quote(value),
+            ConfValue::Raw(value) => value.clone(),
+        }
+    }
+}
+
+/*
+* A weechat configuration file (e.g relay.conf), edited line by line:
+* comments, order and spacing of the other lines are kept as they are
+*/
+pub struct WeechatConf {
+    pub path: PathBuf,
+    lines: Vec<String>,
+}
+
+enum ConfLine {
+    Section(String),
+    Option(String, String),
+    Other,
+}
+
+impl WeechatConf {
+    /*
+     * An empty file when it does not exist yet
+     */
+    pub fn read(path: &Path) -> Result<WeechatConf> {
+        let lines = match path.exists() {
+            true => fs::read_to_string(path)
+                .with_context(|| format!("reading {}", path.display()))?
+                .lines()
+                .map(|line| line.to_string())
+                .collect(),
+            false => vec![],
+        };
+        Ok(WeechatConf {
+            path: path.to_path_buf(),
+            lines,
+        })
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    /*
+     * The value of an option, unquoted
+     */
+    pub fn get(&self, section: &str, option: &str) -> Option<String> {
+        self.find(section, option)
+            .and_then(|i| match parse_line(&self.lines[i]) {
+                ConfLine::Option(_, value) => Some(value),
+                _ => None,
+            })
+    }
+
+    /*
+     * Replaces the option line, or adds it at the end of its section
+     * (the section is added at the end of the file when missing)
+     */
+    pub fn set(&mut self, section: &str, option: &str, value: &ConfValue) {
+        let line = format!("{} = {}", quote_name(option), value.to_conf_string());
+        if let Some(i) = self.find(section, option) {
+            self.lines[i] = line;
+            return;
+        }
+        let mut current_section = None;
+        let mut insert_at = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            match parse_line(line) {
+                ConfLine::Section(name) => {
+                    if name == section {
+                        insert_at = Some(i + 1);
+                    }
+                    current_section = Some(name);
+                }
+                ConfLine::Option(..) if current_section.as_deref() == Some(section) => {
+                    insert_at = Some(i + 1)
+                }
+                _ => {}
+            }
+        }
+        match insert_at {
+            Some(i) => self.lines.insert(i, line),
+            None => {
+                if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{section}]"));
+                self.lines.push(line);
+            }
+        }
+    }
+
+    /*
+     * The options of a section, in file order
+     */
+    pub fn options(&self, section: &str) -> Vec<(String, String)> {
+        let mut current_section = None;
+        let mut options = vec![];
+        for line in self.lines.iter() {
+            match parse_line(line) {
+                ConfLine::Section(name) => current_section = Some(name),
+                ConfLine::Option(name, value) if current_section.as_deref() == Some(section) => {
+                    options.push((name, value))
+                }
+                _ => {}
+            }
+        }
+        options
+    }
+
+    /*
+     * Written to a temporary file first, then renamed over the original
+     * (keeping its permissions, or readable by you only for a new file)
+     */
+    pub fn save(&self) -> Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        }
+        let mode = fs::metadata(&self.path)
+            .map(|m| m.permissions().mode())
+            .unwrap_or(0o600);
+        let tmp_path = PathBuf::from(format!("{}.slackrope", self.path.display()));
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .mode(mode)
+            .open(&tmp_path)
+            .with_context(|| format!("writing {}", tmp_path.display()))?;
+        for line in self.lines.iter() {
+            writeln!(file, "{line}")?;
+        }
+        fs::set_permissions(&tmp_path, fs::Permissions::from_mode(mode))?;
+        fs::rename(&tmp_path, &self.path)
+            .with_context(|| format!("writing {}", self.path.display()))
+    }
+
+    fn find(&self, section: &str, option: &str) -> Option<usize> {
+        let mut current_section = None;
+        for (i, line) in self.lines.iter().enumerate() {
+            match parse_line(line) {
+                ConfLine::Section(name) => current_section = Some(name),
+                ConfLine::Option(name, _)
+                    if name == option && current_section.as_deref() == Some(section) =>
+                {
+                    return Some(i)
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/*
+* "[section]", "name = value" (both possibly quoted), or a comment / blank line
+*/
+fn parse_line(line: &str) -> ConfLine {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return ConfLine::Other;
+    }
+    if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+        return ConfLine::Section(name.to_string());
+    }
+    let (name, rest) = match line.strip_prefix('"') {
+        Some(quoted) => match quoted.split_once("\" =") {
+            Some((name, rest)) => (name.to_string(), format!("={rest}")),
+            None => return ConfLine::Other,
+        },
+        None => match line.split_once(" =") {
+            Some((name, rest)) => (name.trim().to_string(), format!("={rest}")),
+            None => return ConfLine::Other,
+        },
+    };
+    let Some(value) = rest.trim_start().strip_prefix('=') else {
+        return ConfLine::Other;
+    };
+    ConfLine::Option(name, unquote(value.trim()).to_string())
+}
+
+/*
+* Like weechat: the value between the first and the last quote, taken literally
+* (weechat does not escape quotes nor backslashes)
+*/
+fn unquote(value: &str) -> &str {
+    match value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        true => &value[1..value.len() - 1],
+        false => value,
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{value}\"")
+}
+
+/*
+* Option names are quoted when they hold other characters than weechat's usual ones
+*/
+fn quote_name(name: &str) -> String {
+    match name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '#'))
+    {
+        true => name.to_string(),
+        false => quote(name),
+    }
+}
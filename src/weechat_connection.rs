@@ -1,19 +1,44 @@
-pub use crate::environment::{get_config, WEECHAT_HOST, WEECHAT_PASSWORD, WEECHAT_RELAY_PORT};
-use anyhow::Result;
-use weechat_relay_rs::commands::{Command, InitCommand, StrArgument};
+pub use crate::environment::{config, Config};
+use crate::password::{get_weechat_password, read_weechat_password};
+use crate::utils::clean_string;
+use anyhow::{Context, Result};
+use log::debug;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+use weechat_relay_rs::commands::{Command, InfoCommand, InitCommand, PingCommand, StrArgument};
+use weechat_relay_rs::messages::{Event, Identifier, Object};
 use weechat_relay_rs::Connection;
 
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub fn init_connection() -> Result<Connection> {
-    return init_connection_internal(
-        &get_config::<String>(WEECHAT_HOST),
-        &get_config::<String>(WEECHAT_RELAY_PORT),
-        &get_config::<String>(WEECHAT_PASSWORD),
-    );
+    init_connection_internal(
+        &config().weechat_host,
+        config().weechat_relay_port,
+        get_weechat_password(),
+    )
 }
 
-fn init_connection_internal(host: &str, port: &str, password: &str) -> Result<Connection> {
+/*
+* A connection to the relay of a given configuration, e.g another profile's
+*/
+pub fn init_connection_to(config: &Config) -> Result<Connection> {
+    init_connection_internal(
+        &config.weechat_host,
+        config.weechat_relay_port,
+        &read_weechat_password(config)?,
+    )
+}
+
+fn init_connection_internal(host: &str, port: u16, password: &str) -> Result<Connection> {
     let weechat_host = format!("{host}:{port}");
-    let stream = std::net::TcpStream::connect(weechat_host)?;
+    // a remote host may not answer at all: do not wait for the OS timeout
+    let address = weechat_host
+        .to_socket_addrs()?
+        .next()
+        .with_context(|| format!("resolving {weechat_host}"))?;
+    let stream = TcpStream::connect_timeout(&address, CONNECT_TIMEOUT)?;
     let mut connection = Connection { stream };
     let init_command = InitCommand::new(
         Some(StrArgument::new(password).unwrap().to_stringargument()),
@@ -27,3 +52,68 @@ fn init_connection_internal(host: &str, port: &str, password: &str) -> Result<Co
     connection.send_command(&init_command)?;
     Ok(connection)
 }
+
+/*
+* The relay does not answer the init command:
+* a ping round trip tells that weechat is up, and that the password was accepted
+*/
+pub fn ping_connection(connection: &mut Connection) -> Result<bool> {
+    connection.stream.set_read_timeout(Some(PING_TIMEOUT))?;
+    connection.send_command(&Command {
+        id: None,
+        command: PingCommand::new(StrArgument::new("slackrope").unwrap().to_stringargument()),
+    })?;
+    let is_pong = match connection.get_message() {
+        Ok(m) => m.id == Identifier::Event(Event::Pong),
+        Err(e) => {
+            debug!("{:?}", e);
+            false
+        }
+    };
+    connection.stream.set_read_timeout(None)?;
+    Ok(is_pong)
+}
+
+/*
+* The value of a weechat info (e.g "weechat_data_dir"), none when weechat does not know it
+*/
+pub fn get_weechat_info(connection: &mut Connection, name: &str) -> Result<Option<String>> {
+    connection.stream.set_read_timeout(Some(PING_TIMEOUT))?;
+    connection.send_command(&Command {
+        id: None,
+        command: InfoCommand::new(StrArgument::new(name).unwrap().to_stringargument(), vec![]),
+    })?;
+    let value = match connection.get_message() {
+        Ok(m) => match m.objects.first() {
+            Some(Object::Inf(info)) => Some(clean_string(&info.value)).filter(|v| !v.is_empty()),
+            _ => None,
+        },
+        Err(e) => {
+            debug!("{:?}", e);
+            None
+        }
+    };
+    connection.stream.set_read_timeout(None)?;
+    Ok(value)
+}
+
+pub fn is_relay_reachable() -> bool {
+    is_connection_alive(init_connection())
+}
+
+/*
+* Whether the relay of a given configuration is reachable, e.g one just written
+*/
+pub fn is_relay_reachable_with(config: &Config) -> bool {
+    is_connection_alive(init_connection_to(config))
+}
+
+fn is_connection_alive(connection: Result<Connection>) -> bool {
+    match connection {
+        Ok(mut connection) => ping_connection(&mut connection).unwrap_or(false),
+        Err(e) => {
+            debug!("{:?}", e);
+            false
+        }
+    }
+}
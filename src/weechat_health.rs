@@ -1,38 +1,44 @@
 use std::env;
-use std::path::Path;
+use std::time::Duration;
 
 use anyhow::Result;
 use sysinfo::{ProcessExt, System};
 
-use crate::environment::CONFIG_FILE;
-pub use crate::environment::{
-    get_config, APP_NAME, SLACK_REGISTER_BASEURL, SLACK_REGISTER_REDIRECT_URI,
-    SLACK_REGISTER_SCOPE, SLACK_REGISTER_WEESLACK_CLIENT_ID, WEECHAT_HOST, WEECHAT_PASSWORD,
-    WEECHAT_PROGRAM_NAME, WEECHAT_RELAY_PORT, WEE_SLACK_PLUGIN_DIRECTORY,
-    WEE_SLACK_PLUGIN_FILENAME,
+pub use crate::environment::{config, is_remote_mode, APP_NAME};
+use crate::password::{get_exposed_password_config_file, get_weechat_password_source};
+use crate::weechat_conf::{
+    detect_weechat_dirs, get_wee_slack_plugin_directory, WeechatConf, WeechatDirs,
 };
-use crate::utils::sleep;
 use crate::weechat_connection::init_connection;
 use crate::weechat_process::{
-    get_weechat_processes, is_weechat_running, kill_weechat_processes, spawn_weechat_process,
+    get_weechat_log_path, get_weechat_processes, is_weechat_running, spawn_weechat_and_wait_ready,
+    stop_weechat,
 };
 use crate::weechat_slack::check_connection_and_python_wee_slack_plugin;
 
 pub fn print_weechat_health(sys: &mut System) -> Result<()> {
-    let report_lines = build_weechat_health_report(sys)?;
+    let is_weechat_running = is_weechat_running(sys);
+    let report_lines = build_weechat_health_report(sys, is_weechat_running)?;
     for line in report_lines {
         println!("{line}")
     }
+
+    /* if weechat WAS not running,
+     * we prefer to stop the instance we used
+     * for our health tests, etc.
+     */
+    if !is_weechat_running && !is_remote_mode() {
+        stop_weechat(sys, Duration::from_secs(5))?;
+    }
     Ok(())
 }
 
-fn build_weechat_health_report(sys: &mut System) -> Result<Vec<String>> {
+fn build_weechat_health_report(sys: &mut System, is_weechat_running: bool) -> Result<Vec<String>> {
     let mut report_lines = Vec::<String>::new();
     let mut nl = |line| report_lines.push(line);
 
-    let is_weechat_running = is_weechat_running(sys);
     /* WEECHAT INFO */
-    nl(format!("> weechat INFO"));
+    nl("> weechat INFO".to_string());
     nl(format!("is_running: {}", is_weechat_running));
 
     if let Some(processes) = get_weechat_processes(sys) {
@@ -42,10 +48,16 @@ fn build_weechat_health_report(sys: &mut System) -> Result<Vec<String>> {
             nl(format!("process#{i}_pid: {}", process.pid()));
         }
     }
+    nl(format!("log_file: {}", get_weechat_log_path().display()));
 
-    if !is_weechat_running {
-        spawn_weechat_process()?;
-        sleep(2);
+    // a remote weechat is never spawned (nor killed) by slackrope
+    let is_remote_mode = is_remote_mode();
+    nl(format!(
+        "mode: {}",
+        if is_remote_mode { "remote" } else { "local" }
+    ));
+    if !is_weechat_running && !is_remote_mode {
+        spawn_weechat_and_wait_ready()?;
     }
     let (weechat_connection_state, wee_slack_plugin_state) = test_connection_and_plugin();
     nl(format!(
@@ -53,97 +65,76 @@ fn build_weechat_health_report(sys: &mut System) -> Result<Vec<String>> {
         weechat_connection_state
     ));
     nl(format!("weeslack_plugin_test: {}", wee_slack_plugin_state));
-    nl(format!(
-        "weeslack_plugin_install_path: {}",
-        get_wee_slack_plugin_install_path()
-    ));
-    nl(format!(""));
+    if !is_remote_mode {
+        // asked to weechat itself, now that it runs
+        let dirs = detect_weechat_dirs();
+        nl(format!(
+            "weeslack_plugin_install_path: {}",
+            get_wee_slack_plugin_install_path(&dirs)
+        ));
+        nl(format!("weechat_config_dir: {}", dirs.config.display()));
+        nl(format!("weechat_data_dir: {}", dirs.data.display()));
+        nl(format!("weechat_cache_dir: {}", dirs.cache.display()));
+        nl(format!(
+            "weechat_core_log: {}",
+            dirs.data.join("weechat.log").display()
+        ));
+        nl(format!(
+            "weechat_logs_dir: {}",
+            dirs.data.join("logs").display()
+        ));
+        for line in check_relay_conf(&dirs) {
+            nl(line);
+        }
+    }
+    nl(String::new());
 
     /* APP INFO */
     nl(format!("> {APP_NAME} CONFIG"));
     nl(format!("current_exe: {}", get_current_exec_path()));
     nl(format!("current_config: {}", get_current_config_path()));
-    print_app_loaded_config(nl);
-
-    /* if weechat WAS not running,
-     * we prefer to kill the instances we used
-     * for our health tests, etc.
-     */
-    if !is_weechat_running {
-        kill_weechat_processes(sys)?
+    nl(format!(
+        "current_profile: {}",
+        config().profile.as_deref().unwrap_or("none")
+    ));
+    nl(format!(
+        "password_source: {}",
+        get_weechat_password_source()
+    ));
+    if let Some(path) = get_exposed_password_config_file() {
+        nl(format!(
+            "warning: {} holds sr_weechat_password and is readable by other users, run 'chmod 600 {}'",
+            path.display(),
+            path.display()
+        ));
     }
+    print_app_loaded_config(nl);
 
     Ok(report_lines)
 }
 
 fn print_app_loaded_config(mut nl: impl FnMut(String)) {
-    nl(format!(
-        "{WEECHAT_HOST}: {}",
-        get_config::<String>(WEECHAT_HOST)
-    ));
-    nl(format!(
-        "{WEECHAT_RELAY_PORT}: {}",
-        get_config::<String>(WEECHAT_RELAY_PORT)
-    ));
-    nl(format!(
-        "{WEECHAT_PROGRAM_NAME}: {}",
-        get_config::<String>(WEECHAT_PROGRAM_NAME)
-    ));
-    nl(format!(
-        "{WEECHAT_PASSWORD}: {}",
-        get_config::<String>(WEECHAT_PASSWORD)
-            .chars()
-            .map(|_| '*')
-            .collect::<String>()
-    ));
-    nl(format!(
-        "{SLACK_REGISTER_BASEURL}: {}",
-        get_config::<String>(SLACK_REGISTER_BASEURL)
-    ));
-    nl(format!(
-        "{SLACK_REGISTER_WEESLACK_CLIENT_ID}: {}",
-        get_config::<String>(SLACK_REGISTER_WEESLACK_CLIENT_ID)
-    ));
-    nl(format!(
-        "{SLACK_REGISTER_SCOPE}: {}",
-        get_config::<String>(SLACK_REGISTER_SCOPE)
-    ));
-    nl(format!(
-        "{SLACK_REGISTER_REDIRECT_URI}: {}",
-        get_config::<String>(SLACK_REGISTER_REDIRECT_URI)
-    ));
-    nl(format!(
-        "{WEE_SLACK_PLUGIN_DIRECTORY}: {}",
-        get_config::<String>(WEE_SLACK_PLUGIN_DIRECTORY)
-    ));
-    nl(format!(
-        "{WEE_SLACK_PLUGIN_FILENAME}: {}",
-        get_config::<String>(WEE_SLACK_PLUGIN_FILENAME)
-    ));
+    let config = config();
+    for (key, value) in config.entries() {
+        nl(format!("{key}: {value} ({})", config.source(key)));
+    }
 }
 
-fn get_wee_slack_plugin_install_path() -> String {
-    let wee_slack_plugin_directory = get_config::<String>(WEE_SLACK_PLUGIN_DIRECTORY);
-    let wee_slack_plugin_filename = get_config::<String>(WEE_SLACK_PLUGIN_FILENAME);
-    let wee_slack_plugin_filepath =
-        format!("{wee_slack_plugin_directory}/{wee_slack_plugin_filename}",);
-    let wee_slack_plugin_install_path = match Path::new(&wee_slack_plugin_filepath.clone())
-        .try_exists()
-    {
-        Ok(exists) => {
-            if exists {
-                wee_slack_plugin_filepath
-            } else {
-                format!(
-                    "File {wee_slack_plugin_filename} not found in directory {wee_slack_plugin_directory}",
-                )
-            }
-        }
-        Err(_) => {
-            format!("Failure while checking if file {wee_slack_plugin_filepath} exists",)
-        }
-    };
-    wee_slack_plugin_install_path
+fn get_wee_slack_plugin_install_path(dirs: &WeechatDirs) -> String {
+    let wee_slack_plugin_directory = get_wee_slack_plugin_directory(dirs);
+    let wee_slack_plugin_filename = &config().wee_slack_plugin_filename;
+    let wee_slack_plugin_filepath = wee_slack_plugin_directory.join(wee_slack_plugin_filename);
+    match wee_slack_plugin_filepath.try_exists() {
+        Ok(true) => wee_slack_plugin_filepath.display().to_string(),
+        Ok(false) => format!(
+            "File {wee_slack_plugin_filename} not found in directory {}",
+            wee_slack_plugin_directory.display()
+        ),
+        Err(_) => format!(
+            "Failure while checking if file {} exists",
+            wee_slack_plugin_filepath.display()
+        ),
+    }
 }
 
 fn test_connection_and_plugin() -> (String, String) {
@@ -163,6 +154,33 @@ fn test_connection_and_plugin() -> (String, String) {
     )
 }
 
+/*
+* relay.conf, read offline: the port weechat listens on should be slackrope's
+*/
+fn check_relay_conf(dirs: &WeechatDirs) -> Vec<String> {
+    let path = dirs.config.join("relay.conf");
+    let relay = match WeechatConf::read(&path) {
+        Ok(relay) if relay.exists() => relay,
+        _ => return vec![format!("relay_conf: {} not found", path.display())],
+    };
+    let port = config().weechat_relay_port.to_string();
+    let ports: Vec<String> = relay
+        .options("port")
+        .into_iter()
+        .filter(|(name, _)| name == "weechat" || name.ends_with(".weechat"))
+        .filter(|(name, _)| !name.contains("tls.") && !name.contains("ssl."))
+        .map(|(_, value)| value)
+        .collect();
+    let mut lines = vec![format!("relay_conf_ports: {}", ports.join(", "))];
+    if !ports.contains(&port) {
+        lines.push(format!(
+            "warning: {} has no weechat relay on port {port} (sr_weechat_relay_port), see 'slackrope setup-relay'",
+            path.display()
+        ));
+    }
+    lines
+}
+
 fn get_current_exec_path() -> String {
     match env::current_exe() {
         Ok(exe_path) => exe_path.display().to_string(),
@@ -171,8 +189,8 @@ fn get_current_exec_path() -> String {
 }
 
 fn get_current_config_path() -> String {
-    match get_config::<Option<String>>(CONFIG_FILE) {
-        Some(config_path) => config_path,
+    match &config().file {
+        Some(config_path) => config_path.display().to_string(),
         None => "none".to_string(),
     }
 }
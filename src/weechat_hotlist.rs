@@ -1,22 +1,66 @@
 pub use crate::cli::OutputFormat;
-pub use crate::models::{Buffer, Detailed, DetailedHotlist, SimpleHotlist};
-use crate::utils::sleep;
+pub use crate::environment::{config, get_profile_state_dir, load_profile_config, Config};
+pub use crate::models::{Buffer, Detailed, DetailedHotlist, Mute, SimpleHotlist};
+pub use crate::mutes::{get_active_mutes, get_active_mutes_in, is_muted, sweep_expired_mutes};
+use crate::utils::format_local_time;
 pub use crate::utils::{clean_string, match_string};
-pub use crate::weechat_connection::init_connection;
-pub use crate::weechat_process::{is_weechat_running, spawn_weechat_process};
+pub use crate::weechat_buffers::{
+    get_buffer_lines_response, send_buffer_lines_request, send_mark_buffer_read,
+};
+pub use crate::weechat_connection::{init_connection, init_connection_to};
+pub use crate::weechat_notify::sweep_expired_focus;
+pub use crate::weechat_process::{
+    exit_if_remote_mode, is_weechat_running, spawn_weechat_and_wait_ready,
+};
 use anyhow::Result;
 use log::{debug, info};
+use std::process::exit;
+use std::time::Duration;
 use sysinfo::System;
 use weechat_relay_rs::commands::{
     Command, InfolistCommand, InputCommand, PointerOrName, StrArgument,
 };
-use weechat_relay_rs::messages::{InfolistItem, Object, WInfolist};
+use weechat_relay_rs::messages::{InfolistItem, Object, Pointer, WInfolist};
 use weechat_relay_rs::Connection;
 
+/*
+* A hotlist item, as needed to act on its buffer
+* creation_time is in microseconds
+* count is the number of unread lines in the buffer (all priorities)
+*/
+#[derive(Debug, Clone)]
+pub struct HotlistEntry {
+    pub buffer_pointer: Pointer,
+    pub buffer_name: String,
+    pub priority: i32,
+    pub creation_time: i64,
+    pub count: i32,
+}
+
+pub struct NextFlags {
+    pub show: bool,
+    pub mark_read: bool,
+}
+
 pub struct HotlistFlags {
     pub format: OutputFormat,
     pub start: bool,
     pub template: Option<String>,
+    /* profiles to aggregate, all of them when empty */
+    pub aggregate: Option<Vec<String>>,
+}
+
+/* replaced by PARTIAL_MARKER when an aggregated endpoint is unreachable */
+const PARTIAL_PLACEHOLDER: &str = "{{partial}}";
+const PARTIAL_MARKER: &str = "!";
+const AGGREGATE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/*
+* The hotlist of one aggregated endpoint, with the mutes of its profile
+*/
+struct EndpointHotlist {
+    name: String,
+    hotlist: Option<(WInfolist, Vec<Mute>)>,
 }
 
 enum TemplatePriority {
@@ -35,7 +79,13 @@ impl TemplatePriority {
     }
 }
 
-pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
+pub fn hotlist(sys: &mut System, flags: HotlistFlags) -> Result<()> {
+    if let Some(profiles) = flags.aggregate {
+        return print_aggregated_hotlist(&profiles, flags.format, flags.template);
+    }
+    if flags.start {
+        exit_if_remote_mode("hotlist --start");
+    }
     let is_weechat_running = is_weechat_running(sys);
     match flags.template {
         Some(template) => {
@@ -45,13 +95,13 @@ pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
                 return Ok(());
             }
             if !is_weechat_running && flags.start {
-                spawn_weechat_process()?;
-                sleep(2)
+                spawn_weechat_and_wait_ready()?;
             }
             let SimpleHotlist {
                 priority_1: p1,
                 priority_2: p2,
                 priority_3: p3,
+                ..
             } = get_simple_hotlist()?;
             let output = apply_template(
                 &template,
@@ -69,8 +119,7 @@ pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
                 }
 
                 if !is_weechat_running && flags.start {
-                    spawn_weechat_process()?;
-                    sleep(2)
+                    spawn_weechat_and_wait_ready()?;
                 }
                 print_shell_hotlist()?
             }
@@ -81,8 +130,7 @@ pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
                 }
 
                 if !is_weechat_running && flags.start {
-                    spawn_weechat_process()?;
-                    sleep(2)
+                    spawn_weechat_and_wait_ready()?;
                 }
                 print_simple_hotlist()?
             }
@@ -93,8 +141,7 @@ pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
                 }
 
                 if !is_weechat_running && flags.start {
-                    spawn_weechat_process()?;
-                    sleep(2)
+                    spawn_weechat_and_wait_ready()?;
                 }
                 print_detailed_hotlist()?
             }
@@ -103,7 +150,52 @@ pub fn hotlist(sys: &System, flags: HotlistFlags) -> Result<()> {
     Ok(())
 }
 
-pub fn clear_hotlist(sys: &System) -> Result<()> {
+/*
+* Prints the next buffer to handle, like weechat's Alt-A:
+* highest priority first, then oldest first
+*/
+pub fn next_hotlist_buffer(sys: &mut System, flags: NextFlags) -> Result<()> {
+    if !is_weechat_running(sys) {
+        debug!("No next buffer : weechat is currently not running");
+        exit(1);
+    }
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+    let entries = get_hotlist_entries(&mut connection)?;
+    let Some(entry) = order_hotlist_entries(entries).into_iter().next() else {
+        debug!("No next buffer : the hotlist is empty");
+        return Ok(());
+    };
+
+    println!("{}", entry.buffer_name);
+    if flags.show {
+        let count = entry.count.max(1) as usize;
+        send_buffer_lines_request(&mut connection, &entry.buffer_pointer, count)?;
+        for line in get_buffer_lines_response(&mut connection, count)? {
+            println!(
+                "{} {} | {}",
+                format_local_time(line.date, false),
+                line.prefix,
+                line.message
+            );
+        }
+    }
+    if flags.mark_read {
+        send_mark_buffer_read(&mut connection, &entry.buffer_pointer)?;
+    }
+    Ok(())
+}
+
+pub fn order_hotlist_entries(mut entries: Vec<HotlistEntry>) -> Vec<HotlistEntry> {
+    entries.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then(a.creation_time.cmp(&b.creation_time))
+    });
+    entries
+}
+
+pub fn clear_hotlist(sys: &mut System) -> Result<()> {
     if !is_weechat_running(sys) {
         debug!("Did not clear hotlist : weechat is currently not running");
         return Ok(());
@@ -115,9 +207,200 @@ pub fn clear_hotlist(sys: &System) -> Result<()> {
     Ok(())
 }
 
+/*
+* One hotlist out of several weechat endpoints (profiles), queried in parallel.
+* An unreachable endpoint does not fail the hotlist: the counts are partial,
+* and marked so
+*/
+fn print_aggregated_hotlist(
+    profiles: &[String],
+    format: OutputFormat,
+    template: Option<String>,
+) -> Result<()> {
+    let endpoints = fetch_endpoint_hotlists(&get_endpoint_configs(profiles));
+    let unreachable: Vec<String> = endpoints
+        .iter()
+        .filter(|e| e.hotlist.is_none())
+        .map(|e| e.name.clone())
+        .collect();
+    let is_down = unreachable.len() == endpoints.len();
+    let marker = if unreachable.is_empty() {
+        ""
+    } else {
+        PARTIAL_MARKER
+    };
+
+    if template.is_some() || format == OutputFormat::Shell {
+        let SimpleHotlist {
+            priority_1: p1,
+            priority_2: p2,
+            priority_3: p3,
+            ..
+        } = merge_simple_hotlists(&endpoints)?;
+        let output = match template {
+            Some(template) if is_down => apply_template(
+                &template.replace(PARTIAL_PLACEHOLDER, marker),
+                "-",
+                "-",
+                "-",
+            ),
+            Some(template) => apply_template(
+                &template.replace(PARTIAL_PLACEHOLDER, marker),
+                &format!("{p1}"),
+                &format!("{p2}"),
+                &format!("{p3}"),
+            ),
+            None if is_down => "-".to_string(),
+            None => format!("{p1} {p2} {p3}{marker}"),
+        };
+        println!("{output}");
+        return Ok(());
+    }
+    if format == OutputFormat::Simple {
+        let mut simple_hotlist = merge_simple_hotlists(&endpoints)?;
+        simple_hotlist.unreachable = unreachable;
+        println!("{}", serde_json::to_string(&simple_hotlist)?);
+    } else {
+        let mut detailed_hotlist = merge_detailed_hotlists(&endpoints)?;
+        detailed_hotlist.unreachable = unreachable;
+        println!("{}", serde_json::to_string_pretty(&detailed_hotlist)?);
+    }
+    Ok(())
+}
+
+/*
+* The configuration of each profile, all of them by default
+*/
+fn get_endpoint_configs(profiles: &[String]) -> Vec<(String, Config)> {
+    let profiles = match profiles.is_empty() {
+        true => config().profiles.clone(),
+        false => profiles.to_vec(),
+    };
+    if profiles.is_empty() {
+        println!("Error : hotlist --aggregate needs [profiles.<name>] tables in slackrope.toml");
+        exit(1);
+    }
+    profiles
+        .into_iter()
+        .map(
+            |name| match load_profile_config(&name, "hotlist --aggregate") {
+                Ok(config) => (name, config),
+                Err(errors) => {
+                    for error in errors {
+                        println!("Error : {error}");
+                    }
+                    exit(1);
+                }
+            },
+        )
+        .collect()
+}
+
+fn fetch_endpoint_hotlists(endpoints: &[(String, Config)]) -> Vec<EndpointHotlist> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = endpoints
+            .iter()
+            .map(|(name, config)| (name, scope.spawn(|| fetch_endpoint_hotlist(config))))
+            .collect();
+        handles
+            .into_iter()
+            .map(|(name, handle)| {
+                let hotlist = match handle.join() {
+                    Ok(Ok(hotlist)) => hotlist,
+                    Ok(Err(e)) => {
+                        debug!("{name}: {:?}", e);
+                        None
+                    }
+                    Err(_) => None,
+                };
+                if hotlist.is_none() {
+                    info!("{name}: the weechat relay is unreachable");
+                }
+                EndpointHotlist {
+                    name: name.clone(),
+                    hotlist,
+                }
+            })
+            .collect()
+    })
+}
+
+fn fetch_endpoint_hotlist(config: &Config) -> Result<Option<(WInfolist, Vec<Mute>)>> {
+    let mut connection = init_connection_to(config)?;
+    // a stuck endpoint must not hold the others' counts
+    connection
+        .stream
+        .set_read_timeout(Some(AGGREGATE_TIMEOUT))?;
+    send_hotlist_request(&mut connection)?;
+    let hotlist = get_hotlist_response(&mut connection)?;
+    let mutes = match &config.profile {
+        Some(profile) => get_active_mutes_in(&get_profile_state_dir(profile)),
+        None => vec![],
+    };
+    Ok(hotlist.map(|hotlist| (hotlist, mutes)))
+}
+
+/*
+* The counts of the reachable endpoints, summed
+*/
+fn merge_simple_hotlists(endpoints: &[EndpointHotlist]) -> Result<SimpleHotlist> {
+    let mut merged = build_simple_hotlist(&None, &[])?;
+    for (hotlist, mutes) in endpoints.iter().filter_map(|e| e.hotlist.as_ref()) {
+        let simple_hotlist = build_simple_hotlist(&Some(hotlist.clone()), mutes)?;
+        if merged.priority_1 < 0 {
+            merged = simple_hotlist;
+            continue;
+        }
+        merged.priority_1 += simple_hotlist.priority_1;
+        merged.priority_2 += simple_hotlist.priority_2;
+        merged.priority_3 += simple_hotlist.priority_3;
+    }
+    Ok(merged)
+}
+
+/*
+* The items of the reachable endpoints, each with its source
+*/
+fn merge_detailed_hotlists(endpoints: &[EndpointHotlist]) -> Result<DetailedHotlist> {
+    let mut merged = build_detailed_hotlist(&None, &[])?;
+    for endpoint in endpoints.iter() {
+        let Some((hotlist, mutes)) = &endpoint.hotlist else {
+            continue;
+        };
+        let detailed_hotlist = build_detailed_hotlist(&Some(hotlist.clone()), mutes)?;
+        for (merged, detailed) in [
+            (&mut merged.priority_1, detailed_hotlist.priority_1),
+            (&mut merged.priority_2, detailed_hotlist.priority_2),
+            (&mut merged.priority_3, detailed_hotlist.priority_3),
+        ] {
+            merged.count = merged.count.max(0) + detailed.count;
+            merged
+                .items
+                .extend(detailed.items.into_iter().map(|item| Buffer {
+                    source: Some(endpoint.name.clone()),
+                    ..item
+                }));
+        }
+    }
+    Ok(merged)
+}
+
+/*
+* Undoes the expired mutes and focus in weechat, a failure must not hide the hotlist
+*/
+fn sweep_expired_state(connection: &mut Connection) {
+    if let Err(e) = sweep_expired_mutes(connection) {
+        debug!("{:?}", e);
+    }
+    if let Err(e) = sweep_expired_focus(connection) {
+        debug!("{:?}", e);
+    }
+}
+
 fn apply_template(template: &str, priority_1: &str, priority_2: &str, priority_3: &str) -> String {
     template
         .to_string()
+        .replace(PARTIAL_PLACEHOLDER, "")
         .replace(r"\e", "\x1b")
         .replace(r"\033", "\x1b")
         .replace(r"\u001b", "\x1b")
@@ -131,11 +414,12 @@ fn apply_template(template: &str, priority_1: &str, priority_2: &str, priority_3
 fn get_simple_hotlist() -> Result<SimpleHotlist> {
     let mut connection = init_connection()?;
     debug!("connection initiated");
+    sweep_expired_state(&mut connection);
     send_hotlist_request(&mut connection)?;
     debug!("hotlist request sent");
     let hotlist = get_hotlist_response(&mut connection)?;
     debug!("hotlist response received");
-    build_simple_hotlist(&hotlist)
+    build_simple_hotlist(&hotlist, &get_active_mutes())
 }
 
 fn print_shell_hotlist() -> Result<()> {
@@ -157,28 +441,32 @@ fn print_simple_hotlist() -> Result<()> {
 fn print_detailed_hotlist() -> Result<()> {
     let mut connection = init_connection()?;
     debug!("connection initiated");
+    sweep_expired_state(&mut connection);
     send_hotlist_request(&mut connection)?;
     debug!("hotlist request sent");
     let hotlist = get_hotlist_response(&mut connection)?;
     debug!("hotlist response received");
 
-    let detailed_hotlist = build_detailed_hotlist(&hotlist)?;
+    let detailed_hotlist = build_detailed_hotlist(&hotlist, &get_active_mutes())?;
     let serialized = serde_json::to_string_pretty(&detailed_hotlist).unwrap();
     println!("{}", serialized);
     Ok(())
 }
 
-fn send_hotlist_request(connection: &mut Connection) -> Result<()> {
+pub fn build_hotlist_command(id: Option<&str>) -> Command<InfolistCommand> {
     let info_command = InfolistCommand::new(
         StrArgument::new("hotlist").unwrap().to_stringargument(),
         None,
         vec![],
     );
-    let info_command: Command<InfolistCommand> = Command {
-        id: None,
+    Command {
+        id: id.map(|id| StrArgument::new(id).unwrap().to_stringargument()),
         command: info_command,
-    };
-    connection.send_command(&info_command)?;
+    }
+}
+
+pub fn send_hotlist_request(connection: &mut Connection) -> Result<()> {
+    connection.send_command(&build_hotlist_command(None))?;
     Ok(())
 }
 
@@ -202,11 +490,11 @@ fn send_clear_hotlist_request(connection: &mut Connection) -> Result<()> {
     Ok(())
 }
 
-fn get_hotlist_response(connection: &mut Connection) -> Result<Option<WInfolist>> {
+pub fn get_hotlist_response(connection: &mut Connection) -> Result<Option<WInfolist>> {
     let message = connection.get_message();
     match message {
         Ok(m) => {
-            if m.objects.len() > 0 {
+            if !m.objects.is_empty() {
                 match &m.objects[0] {
                     Object::Inl(infolist) => Ok(Some(infolist.clone())),
                     _ => {
@@ -226,13 +514,16 @@ fn get_hotlist_response(connection: &mut Connection) -> Result<Option<WInfolist>
     }
 }
 
-fn build_simple_hotlist(hotlist: &Option<WInfolist>) -> Result<SimpleHotlist> {
+fn build_simple_hotlist(hotlist: &Option<WInfolist>, mutes: &[Mute]) -> Result<SimpleHotlist> {
     match hotlist {
         Some(hotlist) => {
             let mut priority_1 = 0;
             let mut priority_2 = 0;
             let mut priority_3 = 0;
             for item in hotlist.items.iter() {
+                if is_muted_item(item, mutes) {
+                    continue;
+                }
                 for variable in item.variables.iter() {
                     if match_string(&variable.name, "priority") {
                         if let Some(v) = match variable.value {
@@ -240,9 +531,9 @@ fn build_simple_hotlist(hotlist: &Option<WInfolist>) -> Result<SimpleHotlist> {
                             _ => None,
                         } {
                             match v {
-                                3 => count_simple(&item, &mut priority_3, 3),
-                                2 => count_simple(&item, &mut priority_2, 2),
-                                1 => count_simple(&item, &mut priority_1, 1),
+                                3 => count_simple(item, &mut priority_3, 3),
+                                2 => count_simple(item, &mut priority_2, 2),
+                                1 => count_simple(item, &mut priority_1, 1),
                                 _ => {}
                             }
                         }
@@ -253,17 +544,19 @@ fn build_simple_hotlist(hotlist: &Option<WInfolist>) -> Result<SimpleHotlist> {
                 priority_1,
                 priority_2,
                 priority_3,
+                unreachable: vec![],
             })
         }
         None => Ok(SimpleHotlist {
             priority_1: -1,
             priority_2: -1,
             priority_3: -1,
+            unreachable: vec![],
         }),
     }
 }
 
-fn build_detailed_hotlist(hotlist: &Option<WInfolist>) -> Result<DetailedHotlist> {
+fn build_detailed_hotlist(hotlist: &Option<WInfolist>, mutes: &[Mute]) -> Result<DetailedHotlist> {
     match hotlist {
         Some(hotlist) => {
             let mut priority_1 = 0;
@@ -273,6 +566,9 @@ fn build_detailed_hotlist(hotlist: &Option<WInfolist>) -> Result<DetailedHotlist
             let mut buffers_2: Vec<Buffer> = vec![];
             let mut buffers_3: Vec<Buffer> = vec![];
             for item in hotlist.items.iter() {
+                if is_muted_item(item, mutes) {
+                    continue;
+                }
                 for variable in item.variables.iter() {
                     if match_string(&variable.name, "priority") {
                         if let Some(v) = match variable.value {
@@ -280,9 +576,9 @@ fn build_detailed_hotlist(hotlist: &Option<WInfolist>) -> Result<DetailedHotlist
                             _ => None,
                         } {
                             match v {
-                                3 => count_detailed(&item, &mut priority_3, &mut buffers_3, 3),
-                                2 => count_detailed(&item, &mut priority_2, &mut buffers_2, 2),
-                                1 => count_detailed(&item, &mut priority_1, &mut buffers_1, 1),
+                                3 => count_detailed(item, &mut priority_3, &mut buffers_3, 3),
+                                2 => count_detailed(item, &mut priority_2, &mut buffers_2, 2),
+                                1 => count_detailed(item, &mut priority_1, &mut buffers_1, 1),
                                 _ => {}
                             }
                         }
@@ -302,6 +598,7 @@ fn build_detailed_hotlist(hotlist: &Option<WInfolist>) -> Result<DetailedHotlist
                     count: priority_3,
                     items: buffers_3,
                 },
+                unreachable: vec![],
             })
         }
         None => Ok(DetailedHotlist {
@@ -317,10 +614,21 @@ fn build_detailed_hotlist(hotlist: &Option<WInfolist>) -> Result<DetailedHotlist
                 count: -1,
                 items: Vec::new(),
             },
+            unreachable: vec![],
         }),
     }
 }
 
+fn is_muted_item(item: &InfolistItem, mutes: &[Mute]) -> bool {
+    if mutes.is_empty() {
+        return false;
+    }
+    item.variables.iter().any(|variable| {
+        match_string(&variable.name, "buffer_name")
+            && matches!(&variable.value, Object::Str(buffer_name) if is_muted(mutes, &clean_string(buffer_name)))
+    })
+}
+
 fn count_simple(item: &InfolistItem, count: &mut i32, priority: u8) {
     if priority != 1 {
         *count += 1
@@ -328,7 +636,7 @@ fn count_simple(item: &InfolistItem, count: &mut i32, priority: u8) {
         for variable in item.variables.iter() {
             if match_string(&variable.name, "buffer_name") {
                 if let Some(buffer_name) = match &variable.value {
-                    Object::Str(buffer_name) => Some(clean_string(&buffer_name)),
+                    Object::Str(buffer_name) => Some(clean_string(buffer_name)),
                     _ => None,
                 } {
                     // Filter only slack-thread buffers
@@ -349,13 +657,14 @@ fn count_detailed(item: &InfolistItem, count: &mut i32, buffers: &mut Vec<Buffer
     for variable in item.variables.iter() {
         if match_string(&variable.name, "buffer_name") {
             if let Some(buffer_name) = match &variable.value {
-                Object::Str(buffer_name) => Some(clean_string(&buffer_name)),
+                Object::Str(buffer_name) => Some(clean_string(buffer_name)),
                 _ => None,
             } {
                 if priority != 1 {
                     *count += 1;
                     buffers.push(Buffer {
                         buffer: buffer_name,
+                        source: None,
                     })
                 } else {
                     // Filter only slack-thread buffers
@@ -367,6 +676,7 @@ fn count_detailed(item: &InfolistItem, count: &mut i32, buffers: &mut Vec<Buffer
                         *count += 1;
                         buffers.push(Buffer {
                             buffer: buffer_name,
+                            source: None,
                         })
                     }
                 }
@@ -374,3 +684,87 @@ fn count_detailed(item: &InfolistItem, count: &mut i32, buffers: &mut Vec<Buffer
         }
     }
 }
+
+pub fn get_hotlist_entries(connection: &mut Connection) -> Result<Vec<HotlistEntry>> {
+    sweep_expired_state(connection);
+    send_hotlist_request(connection)?;
+    debug!("hotlist request sent");
+    let hotlist = get_hotlist_response(connection)?;
+    debug!("hotlist response received");
+    Ok(build_hotlist_entries(&hotlist, &get_active_mutes()))
+}
+
+/*
+* Same selection as the hotlist counters:
+* priority 1 items are kept only for slack-thread buffers
+*/
+pub fn build_hotlist_entries(hotlist: &Option<WInfolist>, mutes: &[Mute]) -> Vec<HotlistEntry> {
+    let mut entries: Vec<HotlistEntry> = vec![];
+    if let Some(hotlist) = hotlist {
+        for item in hotlist.items.iter() {
+            let mut buffer_pointer = None;
+            let mut buffer_name = None;
+            let mut priority = None;
+            let mut creation_time = 0;
+            let mut count = 0;
+            for variable in item.variables.iter() {
+                match (clean_string(&variable.name).as_str(), &variable.value) {
+                    ("buffer_pointer", Object::Ptr(p)) => buffer_pointer = Some(p.clone()),
+                    ("buffer_name", Object::Str(name)) => buffer_name = Some(clean_string(name)),
+                    ("priority", Object::Int(p)) => priority = Some(*p),
+                    ("creation_time", Object::Buf(Some(bytes))) => {
+                        creation_time = parse_timeval(bytes)
+                    }
+                    (name, Object::Int(c)) if name.starts_with("count_") => count += c,
+                    _ => (),
+                }
+            }
+            if let (Some(buffer_pointer), Some(buffer_name), Some(priority)) =
+                (buffer_pointer, buffer_name, priority)
+            {
+                if is_hotlist_buffer(priority, &buffer_name) && !is_muted(mutes, &buffer_name) {
+                    entries.push(HotlistEntry {
+                        buffer_pointer,
+                        buffer_name,
+                        priority,
+                        creation_time,
+                        count,
+                    })
+                }
+            }
+        }
+    }
+    entries
+}
+
+fn is_hotlist_buffer(priority: i32, buffer_name: &str) -> bool {
+    match priority {
+        2 | 3 => true,
+        // Filter only slack-thread buffers
+        // A slack-thread buffer name has 4 parts separated by
+        // "."
+        // e.g "slack.workspace.#channel.03f"
+        1 => buffer_name.split('.').count() > 3,
+        _ => false,
+    }
+}
+
+/*
+* The hotlist `creation_time` is a raw `struct timeval`,
+* in weechat's native layout (two longs): the relay sends infolist buffers as raw memory
+*/
+fn parse_timeval(bytes: &[u8]) -> i64 {
+    match bytes.len() {
+        16 => {
+            let seconds = i64::from_ne_bytes(bytes[0..8].try_into().unwrap());
+            let microseconds = i64::from_ne_bytes(bytes[8..16].try_into().unwrap());
+            seconds * 1_000_000 + microseconds
+        }
+        8 => {
+            let seconds = i32::from_ne_bytes(bytes[0..4].try_into().unwrap()) as i64;
+            let microseconds = i32::from_ne_bytes(bytes[4..8].try_into().unwrap()) as i64;
+            seconds * 1_000_000 + microseconds
+        }
+        _ => 0,
+    }
+}
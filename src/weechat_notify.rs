@@ -0,0 +1,214 @@
+pub use crate::cli::{FocusAllow, FocusState, NotifyLevel};
+pub use crate::models::{Focus, FocusedBuffer};
+pub use crate::state::{read_state, remove_state, write_state};
+use crate::utils::{format_local_time, now};
+pub use crate::weechat_buffers::{get_buffers, send_buffer_input, BufferInfo};
+pub use crate::weechat_connection::init_connection;
+use anyhow::Result;
+use log::debug;
+use std::process::exit;
+use std::time::Duration;
+use weechat_relay_rs::Connection;
+
+const FOCUS_FILE: &str = "focus.json";
+
+/*
+* Weechat buffer notify levels
+*/
+const NOTIFY_NONE: i32 = 0;
+const NOTIFY_HIGHLIGHT: i32 = 1;
+const NOTIFY_MESSAGE: i32 = 2;
+
+pub struct FocusFlags {
+    pub state: FocusState,
+    pub duration: Option<Duration>,
+    pub allow: Vec<FocusAllow>,
+}
+
+impl NotifyLevel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NotifyLevel::None => "none",
+            NotifyLevel::Highlight => "highlight",
+            NotifyLevel::Message => "message",
+            NotifyLevel::All => "all",
+            NotifyLevel::Reset => "reset",
+        }
+    }
+
+    /* the levels the `buffer` infolist reports */
+    fn from_level(level: i32) -> NotifyLevel {
+        match level {
+            NOTIFY_NONE => NotifyLevel::None,
+            NOTIFY_HIGHLIGHT => NotifyLevel::Highlight,
+            NOTIFY_MESSAGE => NotifyLevel::Message,
+            _ => NotifyLevel::All,
+        }
+    }
+}
+
+pub fn set_buffer_notify(buffer_name: &str, level: NotifyLevel) -> Result<()> {
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+    let buffers = get_buffers(&mut connection, None)?;
+    let Some(buffer) = buffers.iter().find(|b| b.matches(buffer_name)) else {
+        println!("Error : could not find buffer {buffer_name}");
+        exit(1);
+    };
+    send_buffer_input(
+        &mut connection,
+        &buffer.pointer,
+        &format!("/buffer notify {}", level.as_str()),
+    )?;
+    println!("Notify level of {} set to {}", buffer.name, level.as_str());
+    Ok(())
+}
+
+pub fn focus(flags: FocusFlags) -> Result<()> {
+    match flags.state {
+        FocusState::On => start_focus(flags),
+        FocusState::Off => stop_focus(),
+        FocusState::Status => print_focus_status(),
+    }
+}
+
+fn start_focus(flags: FocusFlags) -> Result<()> {
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+    sweep_expired_focus(&mut connection)?;
+    if let Some(focus) = read_state::<Focus>(FOCUS_FILE)? {
+        println!(
+            "Focus mode is already on {}, use 'focus off' first",
+            format_until(focus.until)
+        );
+        exit(1);
+    }
+
+    // highlights still notify, when allowed
+    let level = if flags.allow.contains(&FocusAllow::Highlight) {
+        NOTIFY_HIGHLIGHT
+    } else {
+        NOTIFY_NONE
+    };
+    let until = flags.duration.map(|d| now() + d.as_secs());
+    let mut focused: Vec<FocusedBuffer> = vec![];
+    for buffer in get_buffers(&mut connection, Some("python.*"))? {
+        // only wee-slack's channels, not its team buffers nor other scripts' buffers
+        if !buffer.is_slack_channel() || buffer.notify <= level || is_allowed(&buffer, &flags.allow)
+        {
+            continue;
+        }
+        send_buffer_input(
+            &mut connection,
+            &buffer.pointer,
+            &format!("/buffer notify {}", NotifyLevel::from_level(level).as_str()),
+        )?;
+        focused.push(FocusedBuffer {
+            full_name: buffer.full_name,
+            notify: buffer.notify,
+        });
+    }
+
+    println!(
+        "Focus mode on {}, {} buffer(s) silenced",
+        format_until(until),
+        focused.len()
+    );
+    write_state(
+        FOCUS_FILE,
+        &Focus {
+            until,
+            buffers: focused,
+        },
+    )
+}
+
+/*
+* An expired focus is restored as well
+*/
+fn stop_focus() -> Result<()> {
+    let Some(focus) = read_state::<Focus>(FOCUS_FILE)? else {
+        println!("Focus mode is off");
+        return Ok(());
+    };
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+    restore_notify_levels(&mut connection, &focus.buffers)?;
+    remove_state(FOCUS_FILE)?;
+    println!("Focus mode off, {} buffer(s) restored", focus.buffers.len());
+    Ok(())
+}
+
+fn print_focus_status() -> Result<()> {
+    if read_state::<Focus>(FOCUS_FILE)?.is_some_and(|focus| is_expired(&focus)) {
+        match init_connection() {
+            Ok(mut connection) => sweep_expired_focus(&mut connection)?,
+            Err(e) => debug!("{:?}", e),
+        }
+    }
+    match read_state::<Focus>(FOCUS_FILE)? {
+        Some(focus) => println!(
+            "Focus mode is on {}, {} buffer(s) silenced",
+            format_until(focus.until),
+            focus.buffers.len()
+        ),
+        None => println!("Focus mode is off"),
+    }
+    Ok(())
+}
+
+/*
+* Sets back the notify level of buffers a focus (or a mute) lowered
+*/
+pub fn restore_notify_levels(connection: &mut Connection, focused: &[FocusedBuffer]) -> Result<()> {
+    let buffers = get_buffers(connection, Some("python.*"))?;
+    for focused in focused.iter() {
+        if let Some(buffer) = buffers.iter().find(|b| b.full_name == focused.full_name) {
+            send_buffer_input(
+                connection,
+                &buffer.pointer,
+                &format!(
+                    "/buffer notify {}",
+                    NotifyLevel::from_level(focused.notify).as_str()
+                ),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/*
+* `/buffer notify` is saved by weechat (it survives a restart),
+* so slackrope restores the levels of an expired focus itself
+*/
+pub fn sweep_expired_focus(connection: &mut Connection) -> Result<()> {
+    match read_state::<Focus>(FOCUS_FILE)? {
+        Some(focus) if is_expired(&focus) => {
+            restore_notify_levels(connection, &focus.buffers)?;
+            remove_state(FOCUS_FILE)?;
+            debug!("expired focus restored");
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn is_expired(focus: &Focus) -> bool {
+    focus.until.is_some_and(|until| until <= now())
+}
+
+fn is_allowed(buffer: &BufferInfo, allow: &[FocusAllow]) -> bool {
+    allow.iter().any(|category| match category {
+        FocusAllow::Dm => buffer.is_private(),
+        // A slack-thread buffer name has 4 parts separated by "."
+        FocusAllow::Thread => buffer.name.split('.').count() > 3,
+        FocusAllow::Highlight => false,
+    })
+}
+
+fn format_until(until: Option<u64>) -> String {
+    match until {
+        Some(until) => format!("until {}", format_local_time(until, true)),
+        None => "until 'focus off'".to_string(),
+    }
+}
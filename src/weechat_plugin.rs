@@ -0,0 +1,360 @@
+pub use crate::cli::PluginAction;
+pub use crate::environment::{config, is_remote_mode};
+use crate::weechat_buffers::ask_core_buffer;
+use crate::weechat_conf::{detect_weechat_dirs, get_wee_slack_plugin_directory};
+use crate::weechat_connection::{get_weechat_info, init_connection};
+use crate::weechat_process::{
+    exit_if_remote_mode, is_weechat_running, spawn_weechat_and_wait_ready,
+};
+use crate::weechat_slack::{build_input_command, get_wee_slack_plugin_version};
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command, Stdio};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use weechat_relay_rs::Connection;
+
+const LOAD_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/* how wee_slack.py names itself (SCRIPT_NAME) */
+const WEE_SLACK_MARKER: &str = "SCRIPT_NAME = \"slack\"";
+/* the core buffer local variable weechat's python writes its websocket check to */
+const WEBSOCKET_LOCALVAR: &str = "slackrope_websocket";
+const WEBSOCKET_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+pub fn plugin_command(
+    sys: &mut System,
+    action: PluginAction,
+    path: Option<PathBuf>,
+    force: bool,
+) -> Result<()> {
+    match action {
+        PluginAction::Status => print_plugin_status(sys),
+        PluginAction::Install => install_plugin(sys, &path.unwrap_or_default(), force),
+        PluginAction::Autoload => autoload_plugin(force),
+        PluginAction::Reload => reload_plugin(sys),
+    }
+}
+
+fn print_plugin_status(sys: &mut System) -> Result<()> {
+    // weechat is not started for a status
+    let mut connection = match is_weechat_running(sys) || is_remote_mode() {
+        true => init_connection().ok(),
+        false => None,
+    };
+
+    if !is_remote_mode() {
+        let plugin_path = get_plugin_path();
+        println!(
+            "install_path: {} ({})",
+            plugin_path.display(),
+            if plugin_path.exists() {
+                "ok"
+            } else {
+                "not found"
+            }
+        );
+        println!("autoload: {}", get_autoload_state(&plugin_path));
+    }
+
+    match connection.as_mut() {
+        Some(connection) => {
+            match get_weechat_info(connection, "python_version")? {
+                Some(version) => println!("python: weechat runs python {version}"),
+                None => println!("python: unknown, weechat's python plugin is not loaded"),
+            }
+            println!("websocket_module: {}", get_websocket_state(connection)?);
+        }
+        None if !is_remote_mode() => {
+            println!(
+                "websocket_module: {}",
+                get_host_websocket_state(&get_python_program(None))
+            );
+        }
+        None => {}
+    }
+
+    let loaded = match connection.as_mut() {
+        Some(connection) => match get_wee_slack_plugin_version(connection)? {
+            Some(version) => format!("yes, version {version}"),
+            None => "no, see 'slackrope plugin reload'".to_string(),
+        },
+        None => "unknown, weechat is not running".to_string(),
+    };
+    println!("loaded: {loaded}");
+    Ok(())
+}
+
+/*
+* Copies the file next to itself first, then renames it:
+* a running weechat never sees a partial script
+*/
+fn install_plugin(sys: &mut System, source: &Path, force: bool) -> Result<()> {
+    exit_if_remote_mode("plugin install");
+    let content = match fs::read_to_string(source) {
+        Ok(content) => content,
+        Err(error) => {
+            println!("Error : cannot read {}: {error}", source.display());
+            exit(1);
+        }
+    };
+    if !content.contains(WEE_SLACK_MARKER) && !force {
+        println!(
+            "Error : {} does not look like wee_slack.py (use --force to install it anyway)",
+            source.display()
+        );
+        exit(1);
+    }
+
+    let plugin_path = get_plugin_path();
+    if let Some(dir) = plugin_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    let tmp_path = PathBuf::from(format!("{}.slackrope", plugin_path.display()));
+    fs::write(&tmp_path, content).with_context(|| format!("writing {}", tmp_path.display()))?;
+    fs::rename(&tmp_path, &plugin_path)
+        .with_context(|| format!("writing {}", plugin_path.display()))?;
+    println!("Installed {}", plugin_path.display());
+
+    if !get_autoload_path(&plugin_path).exists() {
+        println!("Run 'slackrope plugin autoload' to load it when weechat starts");
+    }
+    if is_weechat_running(sys) {
+        println!("Run 'slackrope plugin reload' to load it in the running weechat");
+    }
+    Ok(())
+}
+
+/*
+* Like weechat's /script autoload: python/autoload/wee_slack.py -> ../wee_slack.py
+*/
+fn autoload_plugin(force: bool) -> Result<()> {
+    exit_if_remote_mode("plugin autoload");
+    let plugin_path = get_plugin_path();
+    if !plugin_path.exists() {
+        println!(
+            "Error : {} not found, see 'slackrope plugin install'",
+            plugin_path.display()
+        );
+        exit(1);
+    }
+    let autoload_path = get_autoload_path(&plugin_path);
+    let target = get_autoload_target(&plugin_path);
+    if let Ok(metadata) = fs::symlink_metadata(&autoload_path) {
+        if fs::read_link(&autoload_path).is_ok_and(|link| link == target) {
+            println!("{} already autoloads", plugin_path.display());
+            return Ok(());
+        }
+        if !force || metadata.is_dir() {
+            println!(
+                "Error : {} already exists (use --force to replace it)",
+                autoload_path.display()
+            );
+            exit(1);
+        }
+        fs::remove_file(&autoload_path)
+            .with_context(|| format!("removing {}", autoload_path.display()))?;
+    }
+    if let Some(dir) = autoload_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    std::os::unix::fs::symlink(&target, &autoload_path)
+        .with_context(|| format!("creating {}", autoload_path.display()))?;
+    println!(
+        "Created {} -> {}",
+        autoload_path.display(),
+        target.display()
+    );
+    Ok(())
+}
+
+/*
+* /python reload when wee-slack is loaded, else /python load,
+* then waits for the python_script infolist to list it
+*/
+fn reload_plugin(sys: &mut System) -> Result<()> {
+    if !is_weechat_running(sys) {
+        spawn_weechat_and_wait_ready()?;
+    }
+    let mut connection = init_connection()?;
+    let command = match get_wee_slack_plugin_version(&mut connection)? {
+        Some(_) => "/python reload slack".to_string(),
+        None if is_remote_mode() => format!("/python load {}", config().wee_slack_plugin_filename),
+        None => format!("/python load {}", get_plugin_path().display()),
+    };
+    println!("Running {command}...");
+    connection.send_command(&build_input_command("core.weechat", &command))?;
+    match wait_for_wee_slack(&mut connection) {
+        Ok(version) => println!("wee-slack {version} is loaded"),
+        Err(error) => {
+            println!("Error : {error:#}");
+            exit(1);
+        }
+    }
+    Ok(())
+}
+
+/*
+* Reloads wee-slack in the running weechat (e.g once its options changed),
+* weechat itself keeps running
+*/
+pub fn reload_wee_slack(connection: &mut Connection) -> Result<String> {
+    connection.send_command(&build_input_command("core.weechat", "/python reload slack"))?;
+    wait_for_wee_slack(connection)
+}
+
+/*
+* Until the python_script infolist lists wee-slack, an error on the deadline
+*/
+fn wait_for_wee_slack(connection: &mut Connection) -> Result<String> {
+    let deadline = Instant::now() + LOAD_TIMEOUT;
+    loop {
+        if let Some(version) = get_wee_slack_plugin_version(connection)? {
+            return Ok(version);
+        }
+        if Instant::now() > deadline {
+            break;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    match get_load_failure_hint(connection) {
+        Some(hint) => bail!("wee-slack is not loaded, see weechat's core buffer ({hint})"),
+        None => bail!("wee-slack is not loaded, see weechat's core buffer"),
+    }
+}
+
+/*
+* A missing websocket module is the usual reason wee-slack does not load
+*/
+fn get_load_failure_hint(connection: &mut Connection) -> Option<String> {
+    match get_websocket_state(connection) {
+        Ok(state) if !state.starts_with("ok") => Some(format!("websocket_module: {state}")),
+        Ok(_) => None,
+        Err(e) => {
+            debug!("{:?}", e);
+            None
+        }
+    }
+}
+
+/*
+* Whether weechat's own python can import websocket, else the host python's answer
+*/
+fn get_websocket_state(connection: &mut Connection) -> Result<String> {
+    match check_weechat_websocket_module(connection)? {
+        Some(true) => Ok("ok".to_string()),
+        Some(false) => {
+            let python_version = get_weechat_info(connection, "python_version")?;
+            let python = get_python_program(python_version.as_deref());
+            Ok(format!(
+                "missing in weechat's python, e.g run '{python} -m pip install --user websocket-client'"
+            ))
+        }
+        None if is_remote_mode() => Ok("unknown, weechat's python did not answer".to_string()),
+        None => {
+            let python_version = get_weechat_info(connection, "python_version")?;
+            let python = get_python_program(python_version.as_deref());
+            Ok(get_host_websocket_state(&python))
+        }
+    }
+}
+
+/*
+* Runs the check in weechat's embedded python (/python eval, weechat >= 2.9)
+*/
+fn check_weechat_websocket_module(connection: &mut Connection) -> Result<Option<bool>> {
+    let answer = ask_core_buffer(
+        connection,
+        WEBSOCKET_LOCALVAR,
+        |nonce| {
+            format!(
+                "/mute /python eval import importlib.util, weechat; \
+                 weechat.buffer_set(weechat.buffer_search_main(), \
+                 'localvar_set_{WEBSOCKET_LOCALVAR}', '{nonce} ' + \
+                 ('ok' if importlib.util.find_spec('websocket') else 'missing'))"
+            )
+        },
+        WEBSOCKET_CHECK_TIMEOUT,
+    )?;
+    Ok(answer.map(|answer| answer == "ok"))
+}
+
+fn get_plugin_path() -> PathBuf {
+    get_wee_slack_plugin_directory(&detect_weechat_dirs()).join(&config().wee_slack_plugin_filename)
+}
+
+fn get_autoload_path(plugin_path: &Path) -> PathBuf {
+    let dir = plugin_path.parent().unwrap_or(Path::new("."));
+    dir.join("autoload")
+        .join(&config().wee_slack_plugin_filename)
+}
+
+fn get_autoload_target(plugin_path: &Path) -> PathBuf {
+    Path::new("..").join(plugin_path.file_name().unwrap_or_default())
+}
+
+fn get_autoload_state(plugin_path: &Path) -> String {
+    let autoload_path = get_autoload_path(plugin_path);
+    match fs::read_link(&autoload_path) {
+        Ok(link) if link == get_autoload_target(plugin_path) => {
+            format!("{} (ok)", autoload_path.display())
+        }
+        Ok(link) => format!(
+            "{} (points to {}, use 'slackrope plugin autoload --force')",
+            autoload_path.display(),
+            link.display()
+        ),
+        Err(_) if autoload_path.exists() => format!("{} (a copy)", autoload_path.display()),
+        Err(_) => format!(
+            "{} (missing, see 'slackrope plugin autoload')",
+            autoload_path.display()
+        ),
+    }
+}
+
+/*
+* The interpreter matching the python weechat embeds (e.g "3.11.2" -> python3.11)
+*/
+fn get_python_program(python_version: Option<&str>) -> String {
+    let versioned = python_version.and_then(|version| {
+        let mut parts = version.split('.');
+        Some(format!("python{}.{}", parts.next()?, parts.next()?))
+    });
+    match versioned {
+        Some(program) if is_program_available(&program) => program,
+        _ => "python3".to_string(),
+    }
+}
+
+fn is_program_available(program: &str) -> bool {
+    Command::new(program)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/*
+* wee-slack imports websocket (the websocket-client package).
+* The host python only approximates the one weechat embeds (venv, pyenv...)
+*/
+fn get_host_websocket_state(python: &str) -> String {
+    let status = Command::new(python)
+        .args(["-c", "import websocket"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => format!("ok in {python} (host python, approximate)"),
+        Ok(_) => format!(
+            "missing in {python} (host python, approximate), run '{python} -m pip install --user websocket-client'"
+        ),
+        Err(error) => {
+            debug!("{:?}", error);
+            format!("unknown, cannot run {python}")
+        }
+    }
+}
@@ -1,58 +1,585 @@
 use anyhow::{Context, Result};
-use log::debug;
+use log::{debug, info};
 use std::error;
 use std::fmt;
-use sysinfo::{Process, ProcessExt, System, SystemExt};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::time::{Duration, Instant};
+use sysinfo::{
+    Pid, PidExt, Process, ProcessExt, ProcessRefreshKind, ProcessStatus, Signal, System, SystemExt,
+};
 
-use crate::environment::{get_config, WEECHAT_PROGRAM_NAME};
+use crate::environment::{config, expand_path, is_remote_mode, APP_NAME};
+use crate::models::WeechatInstance;
+use crate::state::{lock_state, read_state, remove_state, write_state, StateLock};
+use crate::utils::{format_local_time, now};
+use crate::weechat_connection::{init_connection, is_relay_reachable};
+use crate::weechat_service::{
+    get_service_main_pid, get_service_name, is_service_installed, kill_service, start_service,
+    stop_service,
+};
+use crate::weechat_slack::{get_registered_slack_teams, send_quit_command};
 
-pub fn get_weechat_processes(sys: &System) -> Option<Vec<&Process>> {
-    let program_name = get_config::<String>(WEECHAT_PROGRAM_NAME);
-    let mut vec: Vec<&Process> = Vec::new();
-    for process in sys.processes_by_name("weechat") {
-        let name = process.name();
-        if name == "weechat" || name == program_name {
-            vec.push(process);
+const READY_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+const PID_FILE: &str = "weechat.pid.json";
+const SPAWN_LOCK_FILE: &str = "spawn.lock";
+// rotated copies of the weechat log file to keep
+const LOG_ROTATIONS: usize = 3;
+
+/*
+* The weechat instance slackrope owns: the one its systemd service runs,
+* or else the one recorded in its pidfile
+* (the pid must still run the same command line, as the current user).
+* Only this process is inspected, never the whole process table
+*/
+pub fn get_weechat_processes(sys: &mut System) -> Option<Vec<&Process>> {
+    let mut pid = read_pidfile()
+        .filter(|instance| is_owned_process(sys, instance.pid, Some(&instance.cmd)))
+        .map(|instance| instance.pid);
+    // systemctl only when the pidfile does not match, e.g the service (re)started weechat
+    if pid.is_none() && is_service_installed() {
+        pid = get_service_main_pid().filter(|pid| is_owned_process(sys, *pid, None));
+        if let Some(process) = pid.and_then(|pid| sys.process(Pid::from_u32(pid))) {
+            let instance = WeechatInstance {
+                pid: process.pid().as_u32(),
+                cmd: process.cmd().to_vec(),
+            };
+            if let Err(e) = write_state(PID_FILE, &instance) {
+                debug!("{:?}", e);
+            }
+        }
+    }
+    match pid.and_then(|pid| sys.process(Pid::from_u32(pid))) {
+        Some(process) => {
             debug!(
                 "Found running {} process with pid: {}",
                 process.name(),
                 process.pid()
             );
+            Some(vec![process])
+        }
+        None => {
+            debug!("Did not found any running weechat processes owned by {APP_NAME}");
+            None
         }
     }
-    if vec.len() == 0 {
-        debug!("Did not found any running weechat processes");
-        return None;
+}
+
+/*
+* A live process of the current user, with the given command line when known
+* (a reused pid runs another command)
+*/
+fn is_owned_process(sys: &mut System, pid: u32, cmd: Option<&[String]>) -> bool {
+    let pid = Pid::from_u32(pid);
+    refresh_process(sys, pid)
+        && sys.process(pid).is_some_and(|process| {
+            is_current_user(process)
+                && cmd.is_none_or(|cmd| process.cmd() == cmd)
+                && process.status() != ProcessStatus::Zombie
+        })
+}
+
+fn read_pidfile() -> Option<WeechatInstance> {
+    match read_state::<WeechatInstance>(PID_FILE) {
+        Ok(Some(instance)) => Some(instance),
+        Ok(None) => {
+            debug!("Did not found any weechat pidfile");
+            None
+        }
+        Err(e) => {
+            debug!("{:?}", e);
+            None
+        }
     }
-    Some(vec)
+}
+
+/*
+* Reads a single process (with its owner), false when it is gone
+*/
+fn refresh_process(sys: &mut System, pid: Pid) -> bool {
+    sys.refresh_process_specifics(pid, ProcessRefreshKind::new().with_user())
 }
 
 pub fn kill_weechat_processes(sys: &mut System) -> Result<()> {
     debug!("...kill_weechat_processes ?");
-    sys.refresh_processes();
+    exit_if_remote_mode("kill");
+    if is_service_installed() {
+        kill_service()?;
+        return remove_state(PID_FILE);
+    }
     if let Some(processes) = get_weechat_processes(sys) {
         for process in processes {
             process.kill();
         }
+        remove_state(PID_FILE)?;
         debug!("Done killing weechat processes");
     }
     Ok(())
 }
 
-pub fn is_weechat_running(sys: &System) -> bool {
+/*
+* Records a running weechat (e.g started by hand) as the instance slackrope owns
+*/
+pub fn adopt_weechat_process(sys: &mut System, pid: Option<u32>) -> Result<()> {
+    exit_if_remote_mode("adopt");
+    let pid = match pid {
+        Some(pid) => Pid::from_u32(pid),
+        None => get_relay_port_owner()?,
+    };
+    let Some(process) = refresh_process(sys, pid)
+        .then(|| sys.process(pid))
+        .flatten()
+    else {
+        println!("Error : could not find any process with pid {pid}");
+        exit(1);
+    };
+    if !is_current_user(process) {
+        println!("Error : process {pid} belongs to another user");
+        exit(1);
+    }
+    if !is_weechat_process(process) {
+        println!("Error : process {pid} ({}) is not weechat", process.name());
+        exit(1);
+    }
+
+    write_state(
+        PID_FILE,
+        &WeechatInstance {
+            pid: pid.as_u32(),
+            cmd: process.cmd().to_vec(),
+        },
+    )?;
+    println!(
+        "Adopted weechat process {pid} ({})",
+        process.cmd().join(" ")
+    );
+    Ok(())
+}
+
+/*
+* Graceful stop: /quit over the relay (weechat saves its config and closes
+* the slack websockets), then SIGTERM, then SIGKILL, each after a timeout
+*/
+pub fn stop_weechat(sys: &mut System, timeout: Duration) -> Result<()> {
+    exit_if_remote_mode("stop");
+    if !is_weechat_running(sys) {
+        println!("Weechat is not running");
+        return Ok(());
+    }
+
+    println!("Stopping weechat...");
+    if is_service_installed() {
+        stop_service()?;
+        remove_state(PID_FILE)?;
+        println!("Weechat stopped ({})", get_service_name());
+        return Ok(());
+    }
+    match init_connection() {
+        Ok(mut connection) => send_quit_command(&mut connection)?,
+        Err(e) => debug!("could not send /quit command: {:?}", e),
+    }
+    if wait_for_weechat_exit(sys, timeout) {
+        remove_state(PID_FILE)?;
+        println!("Weechat stopped");
+        return Ok(());
+    }
+
+    debug!("weechat did not quit, sending SIGTERM");
+    signal_weechat_processes(sys, Signal::Term);
+    if wait_for_weechat_exit(sys, timeout) {
+        remove_state(PID_FILE)?;
+        println!("Weechat stopped (SIGTERM)");
+        return Ok(());
+    }
+
+    debug!("weechat did not terminate, sending SIGKILL");
+    kill_weechat_processes(sys)?;
+    println!("Weechat killed (SIGKILL)");
+    Ok(())
+}
+
+pub fn start_weechat(sys: &mut System) -> Result<()> {
+    exit_if_remote_mode("start");
+    if let Some(processes) = get_weechat_processes(sys) {
+        println!(
+            "Weechat is already running (pid {})",
+            format_pids(&processes)
+        );
+        truncate_weechat_log_file();
+        return Ok(());
+    }
+
+    println!("Starting weechat...");
+    spawn_weechat_and_wait_ready()?;
+    if is_relay_reachable() {
+        println!("Weechat is running, its relay is reachable");
+    } else {
+        println!("Weechat was started, but its relay is not reachable.");
+        println!("Please check the relay configuration with the 'health' command.");
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+pub fn restart_weechat(sys: &mut System, timeout: Duration) -> Result<()> {
+    stop_weechat(sys, timeout)?;
+    start_weechat(sys)
+}
+
+pub fn print_weechat_status(sys: &mut System) -> Result<()> {
+    if is_remote_mode() {
+        println!("mode: remote");
+    } else {
+        let Some(processes) = get_weechat_processes(sys) else {
+            println!("is_running: false");
+            if is_relay_reachable() {
+                println!("hint: the relay is answered by a weechat {APP_NAME} did not start, see '{APP_NAME} adopt -h'");
+            }
+            return Ok(());
+        };
+        println!("is_running: true");
+        for process in processes.iter() {
+            println!("pid: {}", process.pid());
+            println!(
+                "uptime: {}",
+                humantime::format_duration(Duration::from_secs(process.run_time()))
+            );
+        }
+    }
+
+    let relay = format!("{}:{}", config().weechat_host, config().weechat_relay_port);
+    if !is_relay_reachable() {
+        println!("relay: unreachable ({relay})");
+        return Ok(());
+    }
+    println!("relay: reachable ({relay})");
+
+    let mut connection = init_connection()?;
+    let teams = get_registered_slack_teams(&mut connection)?;
+    println!(
+        "teams: {}",
+        teams
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ")
+    );
+    Ok(())
+}
+
+/*
+* Polls the relay until it answers (or a timeout)
+*/
+pub fn wait_for_weechat_ready() -> bool {
+    let deadline = Instant::now() + READY_TIMEOUT;
+    while Instant::now() < deadline {
+        if is_relay_reachable() {
+            debug!("weechat relay is ready");
+            return true;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    info!("weechat relay is still not reachable");
+    false
+}
+
+/*
+* Spawns weechat unless it is already running, then waits for its relay.
+* The check-then-spawn runs under an inter-process lock: a concurrent
+* slackrope (e.g another tmux status line) waits for this spawn to be ready
+* instead of spawning a second weechat
+*/
+pub fn spawn_weechat_and_wait_ready() -> Result<()> {
+    if is_remote_mode() {
+        println!(
+            "Error : the weechat relay is not reachable on {}:{}",
+            config().weechat_host,
+            config().weechat_relay_port
+        );
+        exit(1);
+    }
+    let _lock = lock_weechat_spawn()?;
+    // a weechat slackrope does not own may already hold the relay port
+    if is_relay_reachable() {
+        debug!("weechat relay is already reachable, not spawning");
+        return Ok(());
+    }
+    if is_weechat_running(&mut System::new()) {
+        debug!("weechat was spawned by another slackrope, waiting for it");
+    } else {
+        launch_weechat()?;
+    }
+    wait_for_weechat_ready();
+    Ok(())
+}
+
+/*
+* Starts weechat through its systemd service when installed,
+* or else as a child process
+*/
+pub fn launch_weechat() -> Result<()> {
+    if is_service_installed() {
+        // systemd appends to the log file, open_weechat_log_file is not called
+        truncate_weechat_log_file();
+        start_service()
+    } else {
+        spawn_weechat_process().map(|_| ())
+    }
+}
+
+pub fn lock_weechat_spawn() -> Result<StateLock> {
+    debug!("...waiting for the spawn lock");
+    lock_state(SPAWN_LOCK_FILE)
+}
+
+fn wait_for_weechat_exit(sys: &mut System, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if !is_weechat_running(sys) {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        debug!("waiting for weechat to quit...");
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn signal_weechat_processes(sys: &mut System, signal: Signal) {
+    if let Some(processes) = get_weechat_processes(sys) {
+        for process in processes {
+            if process.kill_with(signal).is_none() {
+                debug!(
+                    "signal {signal} is not supported, killing {}",
+                    process.pid()
+                );
+                process.kill();
+            }
+        }
+    }
+}
+
+fn format_pids(processes: &[&Process]) -> String {
+    processes
+        .iter()
+        .map(|p| p.pid())
+        .map(|pid: Pid| pid.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/*
+* In remote mode, weechat is up as long as its relay answers
+*/
+pub fn is_weechat_running(sys: &mut System) -> bool {
     debug!("...is_weechat_running ?");
+    if is_remote_mode() {
+        return is_relay_reachable();
+    }
     get_weechat_processes(sys).is_some()
 }
 
+/*
+* slackrope cannot manage a weechat running on another machine
+*/
+pub fn exit_if_remote_mode(action: &str) {
+    if is_remote_mode() {
+        println!(
+            "Error : '{action}' is disabled in remote mode, weechat runs on {} (see sr_weechat_mode)",
+            config().weechat_host
+        );
+        exit(1);
+    }
+}
+
 pub fn spawn_weechat_process() -> Result<std::process::Child> {
-    let program_name = get_config::<String>(WEECHAT_PROGRAM_NAME);
+    let program_name = config().weechat_program_name.clone();
+    let args = get_weechat_args();
+    let log_file = open_weechat_log_file(&program_name, &args)?;
 
     debug!("...spawning {} process", &program_name);
-    std::process::Command::new(&program_name)
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::null())
-        .spawn()
-        .context(WeechatSpawnFailed { program_name })
+    let mut command = std::process::Command::new(&program_name);
+    command
+        .args(&args)
+        .envs(get_weechat_env())
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file);
+    let working_dir = &config().weechat_working_dir;
+    if !working_dir.is_empty() {
+        command.current_dir(expand_path(working_dir));
+    }
+    let child = command.spawn().context(WeechatSpawnFailed {
+        program_name: program_name.clone(),
+    })?;
+
+    let mut cmd = vec![program_name];
+    cmd.extend(args);
+    write_state(
+        PID_FILE,
+        &WeechatInstance {
+            pid: child.id(),
+            cmd,
+        },
+    )?;
+    Ok(child)
+}
+
+/*
+* The arguments weechat is started with:
+* its profile dir, extra arguments, then one -r per startup command
+*/
+pub fn get_weechat_args() -> Vec<String> {
+    let mut args = vec![];
+    let dir = &config().weechat_dir;
+    if !dir.is_empty() {
+        args.push("--dir".to_string());
+        args.push(expand_path(dir));
+    }
+    args.extend(config().weechat_args.iter().cloned());
+    for command in config().weechat_startup_commands.iter().cloned() {
+        args.push("-r".to_string());
+        args.push(command);
+    }
+    args
+}
+
+/*
+* Environment variables, configured as "NAME=value" (checked when loading the config)
+*/
+pub fn get_weechat_env() -> Vec<(String, String)> {
+    config()
+        .weechat_env
+        .iter()
+        .filter_map(|var| var.split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+pub fn get_weechat_log_path() -> PathBuf {
+    PathBuf::from(expand_path(&config().weechat_log_file))
+}
+
+/*
+* The weechat output is appended to the log file,
+* which is rotated at spawn time once it exceeds its max size
+* (and by truncate_weechat_log_file for the service, or on 'start' while weechat runs)
+*/
+pub fn open_weechat_log_file(program_name: &str, args: &[String]) -> Result<File> {
+    let path = get_weechat_log_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= config().weechat_log_max_size) {
+        rotate_log_file(&path)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening {}", path.display()))?;
+    writeln!(
+        file,
+        "[{}] {APP_NAME}: starting {} {}",
+        format_local_time(now(), true),
+        program_name,
+        args.join(" ")
+    )?;
+    Ok(file)
+}
+
+fn rotate_log_file(path: &Path) -> Result<()> {
+    shift_rotated_log_files(path)?;
+    fs::rename(path, get_rotated_log_path(path, 1))
+        .with_context(|| format!("rotating {}", path.display()))
+}
+
+/*
+* A running weechat keeps its log file open, so it is copied then truncated:
+* weechat (or systemd) appends to it, its next writes start at the beginning
+*/
+fn truncate_weechat_log_file() {
+    let path = get_weechat_log_path();
+    if !fs::metadata(&path).is_ok_and(|m| m.len() >= config().weechat_log_max_size) {
+        return;
+    }
+    let result = shift_rotated_log_files(&path)
+        .and_then(|_| {
+            fs::copy(&path, get_rotated_log_path(&path, 1))
+                .with_context(|| format!("rotating {}", path.display()))
+        })
+        .and_then(|_| {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_len(0))
+                .with_context(|| format!("truncating {}", path.display()))
+        });
+    if let Err(e) = result {
+        debug!("{:?}", e);
+    }
+}
+
+fn shift_rotated_log_files(path: &Path) -> Result<()> {
+    for i in (1..LOG_ROTATIONS).rev() {
+        if get_rotated_log_path(path, i).exists() {
+            fs::rename(
+                get_rotated_log_path(path, i),
+                get_rotated_log_path(path, i + 1),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn get_rotated_log_path(path: &Path, i: usize) -> PathBuf {
+    PathBuf::from(format!("{}.{i}", path.display()))
+}
+
+/*
+* The pid of the process listening on the relay port, through lsof
+*/
+fn get_relay_port_owner() -> Result<Pid> {
+    let port = config().weechat_relay_port;
+    let output = std::process::Command::new("lsof")
+        .args(["-t", &format!("-iTCP:{port}"), "-sTCP:LISTEN"])
+        .output()
+        .context("running lsof, use --pid instead")?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout
+        .lines()
+        .find_map(|line| line.trim().parse::<u32>().ok())
+    {
+        Some(pid) => Ok(Pid::from_u32(pid)),
+        None => {
+            println!("Error : could not find any process listening on relay port {port}");
+            exit(1);
+        }
+    }
+}
+
+fn is_current_user(process: &Process) -> bool {
+    let uid = unsafe { libc::getuid() };
+    process.user_id().is_some_and(|user_id| **user_id == uid)
+}
+
+/*
+* process.name() is truncated by the kernel (e.g "weechat-headles"),
+* so the executable name is checked too
+*/
+fn is_weechat_process(process: &Process) -> bool {
+    let program_name = &config().weechat_program_name;
+    let exe_name = process
+        .cmd()
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .or(process.exe().file_name())
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    exe_name.starts_with("weechat") || &exe_name == program_name
 }
 
 #[derive(Debug)]
@@ -0,0 +1,245 @@
+pub use crate::environment::{config, expand_path, load_config, write_config_values};
+use crate::password::{get_sec_data_name, get_weechat_password};
+use crate::weechat_conf::{get_weechat_conf_path, set_sec_data, ConfValue, WeechatConf};
+use crate::weechat_connection::{init_connection, is_relay_reachable_with};
+use crate::weechat_process::{
+    exit_if_remote_mode, get_weechat_args, get_weechat_env, get_weechat_log_path,
+    is_weechat_running, launch_weechat, lock_weechat_spawn, open_weechat_log_file, stop_weechat,
+    WeechatSpawnFailed,
+};
+use anyhow::{bail, Context, Result};
+use log::debug;
+use std::io::Read;
+use std::net::IpAddr;
+use std::process::{exit, Stdio};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/* the weechat secured data holding the relay password */
+const SEC_DATA_NAME: &str = "relay";
+/* hands the password to weechat, out of its command line and log file */
+const PASSWORD_ENV: &str = "WEECHAT_RELAY_PASSWORD";
+
+pub struct SetupRelayFlags {
+    pub tls_port: Option<u16>,
+    pub unix_socket: Option<String>,
+    pub bind_address: Option<IpAddr>,
+    pub offline: bool,
+}
+
+/*
+* Configures the weechat relay for slackrope, whatever its current state:
+* weechat writes its own relay.conf (run once with -r commands),
+* then slackrope.toml gets the matching values, and the relay is tested
+* (--offline: relay.conf and sec.conf are edited in place, weechat is not started)
+*/
+pub fn setup_relay(sys: &mut System, flags: SetupRelayFlags) -> Result<()> {
+    exit_if_remote_mode("setup-relay");
+    let port = config().weechat_relay_port;
+    let configured_password = get_weechat_password();
+    let password = match configured_password.is_empty() {
+        true => generate_password()?,
+        false => configured_password.to_string(),
+    };
+
+    // weechat saves its whole configuration when it quits: it must not run meanwhile
+    let lock = lock_weechat_spawn()?;
+    if is_weechat_running(sys) {
+        stop_weechat(sys, Duration::from_secs(5))?;
+    } else if init_connection().is_ok() {
+        println!(
+            "Error : port {port} is used by a weechat slackrope does not manage, see 'slackrope adopt'"
+        );
+        exit(1);
+    }
+
+    println!("Configuring the weechat relay (port {port})...");
+    if flags.offline {
+        if let Err(error) = write_relay_conf(port, &flags, &password) {
+            println!("Error : {error:#}");
+            exit(1);
+        }
+    } else if let Err(error) = run_weechat_commands(&build_relay_commands(port, &flags), &password)
+    {
+        println!(
+            "Error : {error:#}, see {}",
+            get_weechat_log_path().display()
+        );
+        exit(1);
+    }
+
+    let mut values = vec![("sr_weechat_relay_port", i64::from(port).into())];
+    if configured_password.is_empty() {
+        values.push((
+            "sr_weechat_password",
+            format!("${{sec.data.{SEC_DATA_NAME}}}").into(),
+        ));
+    }
+    let config_path = write_config_values(&values)?;
+    println!("Wrote {}", config_path.display());
+
+    if flags.offline {
+        drop(lock);
+        println!("Weechat will use its relay on port {port} once started, see 'slackrope start'");
+        return Ok(());
+    }
+
+    let new_config = match load_config() {
+        Ok(new_config) => new_config,
+        Err(errors) => {
+            for error in errors {
+                println!("Error : {error}");
+            }
+            exit(1);
+        }
+    };
+    launch_weechat()?;
+    drop(lock);
+    let deadline = Instant::now() + SETUP_TIMEOUT;
+    while !is_relay_reachable_with(&new_config) {
+        if Instant::now() > deadline {
+            println!(
+                "Error : the weechat relay is not reachable on port {port}, see 'slackrope health'"
+            );
+            exit(1);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    println!("Weechat is running, its relay is reachable on port {port}");
+    Ok(())
+}
+
+/*
+* Deleting each relay before adding it makes the setup safe to run again
+*/
+fn build_relay_commands(port: u16, flags: &SetupRelayFlags) -> Vec<String> {
+    let mut commands = vec![
+        "/relay del weechat".to_string(),
+        format!("/relay add weechat {port}"),
+    ];
+    // weechat >= 4.0 (tls. was ssl. before)
+    if let Some(tls_port) = flags.tls_port {
+        commands.push("/relay del tls.weechat".to_string());
+        commands.push(format!("/relay add tls.weechat {tls_port}"));
+    }
+    if let Some(path) = &flags.unix_socket {
+        commands.push("/relay del unix.weechat".to_string());
+        commands.push(format!("/relay add unix.weechat {}", expand_path(path)));
+    }
+    if let Some(address) = &flags.bind_address {
+        commands.push(format!("/set relay.network.bind_address \"{address}\""));
+    }
+    let sec_data_name = match get_sec_data_name(&config().weechat_password) {
+        // weechat already holds the password
+        Some(name) => name.to_string(),
+        None => {
+            commands.push(format!(
+                "/eval /secure set {SEC_DATA_NAME} ${{env:{PASSWORD_ENV}}}"
+            ));
+            SEC_DATA_NAME.to_string()
+        }
+    };
+    commands.push(format!(
+        "/set relay.network.password \"${{sec.data.{sec_data_name}}}\""
+    ));
+    commands.push("/save".to_string());
+    commands.push("/quit".to_string());
+    commands
+}
+
+/*
+* The offline equivalent of build_relay_commands, for a weechat which cannot run here
+*/
+fn write_relay_conf(port: u16, flags: &SetupRelayFlags, password: &str) -> Result<()> {
+    let config = config();
+    let sec_data_name = match get_sec_data_name(&config.weechat_password) {
+        Some(name) => name.to_string(),
+        None => {
+            let path = set_sec_data(config, SEC_DATA_NAME, password)
+                .context("run setup-relay without --offline")?;
+            println!("Wrote {}", path.display());
+            SEC_DATA_NAME.to_string()
+        }
+    };
+
+    let mut relay = WeechatConf::read(&get_weechat_conf_path(config, "relay.conf"))?;
+    relay.set("port", "weechat", &ConfValue::Raw(port.to_string()));
+    if let Some(tls_port) = flags.tls_port {
+        relay.set("port", "tls.weechat", &ConfValue::Raw(tls_port.to_string()));
+    }
+    if let Some(path) = &flags.unix_socket {
+        relay.set(
+            "path",
+            "unix.weechat",
+            &ConfValue::String(expand_path(path)),
+        );
+    }
+    if let Some(address) = &flags.bind_address {
+        relay.set(
+            "network",
+            "bind_address",
+            &ConfValue::String(address.to_string()),
+        );
+    }
+    relay.set(
+        "network",
+        "password",
+        &ConfValue::String(format!("${{sec.data.{sec_data_name}}}")),
+    );
+    relay.save()?;
+    println!("Wrote {}", relay.path.display());
+    Ok(())
+}
+
+fn run_weechat_commands(commands: &[String], password: &str) -> Result<()> {
+    let program_name = config().weechat_program_name.clone();
+    let mut args = get_weechat_args();
+    for command in commands {
+        args.push("-r".to_string());
+        args.push(command.clone());
+    }
+    let log_file = open_weechat_log_file(&program_name, &args)?;
+
+    debug!("...running {} {}", program_name, args.join(" "));
+    let mut command = std::process::Command::new(&program_name);
+    command
+        .args(&args)
+        .envs(get_weechat_env())
+        .env(PASSWORD_ENV, password)
+        .stdin(Stdio::null())
+        .stdout(log_file.try_clone()?)
+        .stderr(log_file);
+    let working_dir = &config().weechat_working_dir;
+    if !working_dir.is_empty() {
+        command.current_dir(expand_path(working_dir));
+    }
+    let mut child = command.spawn().context(WeechatSpawnFailed {
+        program_name: program_name.clone(),
+    })?;
+
+    let deadline = Instant::now() + SETUP_TIMEOUT;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            if !status.success() {
+                bail!("{program_name} failed ({status})");
+            }
+            return Ok(());
+        }
+        if Instant::now() > deadline {
+            child.kill()?;
+            child.wait()?;
+            bail!("{program_name} did not quit after the relay setup");
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn generate_password() -> Result<String> {
+    let mut bytes = [0u8; 24];
+    std::fs::File::open("/dev/urandom")
+        .and_then(|mut file| file.read_exact(&mut bytes))
+        .context("reading /dev/urandom")?;
+    Ok(bytes.iter().map(|b| format!("{b:02x}")).collect())
+}
@@ -0,0 +1,253 @@
+pub use crate::cli::ServiceAction;
+use crate::environment::{config, expand_path, get_systemd_user_dir};
+use crate::weechat_process::{
+    exit_if_remote_mode, get_weechat_args, get_weechat_env, get_weechat_log_path,
+    is_weechat_running, stop_weechat, wait_for_weechat_ready,
+};
+use anyhow::{Context, Result};
+use log::debug;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{exit, Output};
+use std::time::Duration;
+use sysinfo::System;
+
+/*
+* One service per profile, e.g slackrope-weechat-work.service
+*/
+pub fn get_service_name() -> String {
+    match &config().profile {
+        Some(profile) => format!("slackrope-weechat-{profile}.service"),
+        None => "slackrope-weechat.service".to_string(),
+    }
+}
+
+pub fn service(sys: &mut System, action: ServiceAction) -> Result<()> {
+    if action != ServiceAction::Status {
+        exit_if_remote_mode("service");
+    }
+    match action {
+        ServiceAction::Install => install_service(sys),
+        ServiceAction::Uninstall => uninstall_service(),
+        ServiceAction::Status => print_service_status(),
+    }
+}
+
+pub fn is_service_installed() -> bool {
+    get_unit_path().exists()
+}
+
+/*
+* The pid of the weechat the service runs, if it is active
+*/
+pub fn get_service_main_pid() -> Option<u32> {
+    let service_name = get_service_name();
+    let output = systemctl(&["show", "--property", "MainPID", "--value", &service_name]).ok()?;
+    match String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u32>()
+    {
+        Ok(0) | Err(_) => None,
+        Ok(pid) => Some(pid),
+    }
+}
+
+pub fn start_service() -> Result<()> {
+    let service_name = get_service_name();
+    debug!("...starting {service_name}");
+    run_systemctl(&["start", &service_name])
+}
+
+pub fn stop_service() -> Result<()> {
+    let service_name = get_service_name();
+    debug!("...stopping {service_name}");
+    run_systemctl(&["stop", &service_name])
+}
+
+/*
+* SIGKILL, then stop: systemd would restart the killed weechat otherwise
+*/
+pub fn kill_service() -> Result<()> {
+    let service_name = get_service_name();
+    debug!("...killing {service_name}");
+    run_systemctl(&["kill", "--signal", "SIGKILL", &service_name])?;
+    run_systemctl(&["stop", &service_name])
+}
+
+fn install_service(sys: &mut System) -> Result<()> {
+    let service_name = get_service_name();
+    let unit_path = get_unit_path();
+    let unit = build_unit()?;
+    if !is_service_installed() && is_weechat_running(sys) {
+        // systemd takes over the weechat slackrope started itself
+        stop_weechat(sys, Duration::from_secs(5))?;
+    }
+    if let Some(dir) = unit_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+    fs::write(&unit_path, unit).with_context(|| format!("writing {}", unit_path.display()))?;
+    println!("Wrote {}", unit_path.display());
+
+    if !(try_systemctl(&["daemon-reload"])? && try_systemctl(&["enable", "--now", &service_name])?)
+    {
+        // a unit file left behind would make slackrope defer to systemd
+        fs::remove_file(&unit_path).with_context(|| format!("removing {}", unit_path.display()))?;
+        println!("Removed {}", unit_path.display());
+        exit(1);
+    }
+    println!("Enabled and started {service_name}");
+    if !wait_for_weechat_ready() {
+        println!(
+            "Weechat relay is not reachable yet, see 'systemctl --user status {service_name}'"
+        );
+    }
+    Ok(())
+}
+
+fn uninstall_service() -> Result<()> {
+    let service_name = get_service_name();
+    let unit_path = get_unit_path();
+    if !is_service_installed() {
+        println!("{service_name} is not installed");
+        return Ok(());
+    }
+    run_systemctl(&["disable", "--now", &service_name])?;
+    fs::remove_file(&unit_path).with_context(|| format!("removing {}", unit_path.display()))?;
+    run_systemctl(&["daemon-reload"])?;
+    println!("Removed {}", unit_path.display());
+    Ok(())
+}
+
+fn print_service_status() -> Result<()> {
+    let service_name = get_service_name();
+    let unit_path = get_unit_path();
+    println!("installed: {}", is_service_installed());
+    println!("unit_file: {}", unit_path.display());
+    if !is_service_installed() {
+        return Ok(());
+    }
+    let output = systemctl(&["is-active", &service_name])?;
+    println!("active: {}", String::from_utf8_lossy(&output.stdout).trim());
+    let output = systemctl(&["is-enabled", &service_name])?;
+    println!(
+        "enabled: {}",
+        String::from_utf8_lossy(&output.stdout).trim()
+    );
+    if let Some(pid) = get_service_main_pid() {
+        println!("pid: {pid}");
+    }
+    Ok(())
+}
+
+/*
+* The unit runs weechat exactly like spawn_weechat_process does:
+* same program, arguments, environment and log file
+*/
+fn build_unit() -> Result<String> {
+    let program_name = &config().weechat_program_name;
+    let Some(program_path) = find_program(program_name) else {
+        println!("Error : could not find {program_name} in your PATH");
+        exit(1);
+    };
+    let mut exec_start = vec![program_path.display().to_string()];
+    exec_start.extend(get_weechat_args());
+    let exec_start = exec_start
+        .iter()
+        .map(|arg| quote_unit_value(arg))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    let log_path = get_weechat_log_path();
+    if let Some(dir) = log_path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    }
+
+    let mut unit = vec![
+        "[Unit]".to_string(),
+        "Description=weechat-headless for slackrope".to_string(),
+        "After=network-online.target".to_string(),
+        String::new(),
+        "[Service]".to_string(),
+        "Type=simple".to_string(),
+        format!("ExecStart={exec_start}"),
+    ];
+    for (name, value) in get_weechat_env() {
+        unit.push(format!(
+            "Environment={}",
+            quote_unit_value(&format!("{name}={value}"))
+        ));
+    }
+    // without a configured working directory, the home directory
+    let working_dir = &config().weechat_working_dir;
+    if working_dir.is_empty() {
+        unit.push("WorkingDirectory=~".to_string());
+    } else {
+        unit.push(format!("WorkingDirectory={}", expand_path(working_dir)));
+    }
+    unit.extend([
+        format!("StandardOutput=append:{}", log_path.display()),
+        format!("StandardError=append:{}", log_path.display()),
+        "Restart=on-failure".to_string(),
+        "RestartSec=5s".to_string(),
+        String::new(),
+        "[Install]".to_string(),
+        "WantedBy=default.target".to_string(),
+        String::new(),
+    ]);
+    Ok(unit.join("\n"))
+}
+
+/*
+* systemd unit values: double-quoted, with "%" and "$" specifiers escaped
+*/
+fn quote_unit_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+fn find_program(program_name: &str) -> Option<PathBuf> {
+    if program_name.contains('/') {
+        return fs::canonicalize(program_name).ok();
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(program_name))
+            .find(|path| path.is_file())
+    })
+}
+
+fn get_unit_path() -> PathBuf {
+    get_systemd_user_dir().join(get_service_name())
+}
+
+fn run_systemctl(args: &[&str]) -> Result<()> {
+    if !try_systemctl(args)? {
+        exit(1);
+    }
+    Ok(())
+}
+
+fn try_systemctl(args: &[&str]) -> Result<bool> {
+    let output = systemctl(args)?;
+    if !output.status.success() {
+        println!(
+            "Error : systemctl --user {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.status.success())
+}
+
+fn systemctl(args: &[&str]) -> Result<Output> {
+    debug!("...systemctl --user {}", args.join(" "));
+    std::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .output()
+        .context("running systemctl")
+}
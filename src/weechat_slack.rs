@@ -1,35 +1,244 @@
-pub use crate::environment::get_slack_register_url;
+pub use crate::environment::{config, get_slack_register_url};
 pub use crate::models::SlackTeam;
-use crate::utils::sleep;
+use crate::password::read_private_file;
+use crate::utils::redact_tokens;
 pub use crate::utils::{clean_string, match_string};
+pub use crate::weechat_buffers::{
+    get_buffer_lines_response, get_buffers, send_buffer_lines_request, BufferLine,
+};
+use crate::weechat_conf::{detect_weechat_dirs, get_wee_slack_plugin_directory};
 pub use crate::weechat_connection::init_connection;
-pub use crate::weechat_process::{is_weechat_running, spawn_weechat_process};
-use anyhow::Result;
+use crate::weechat_plugin::reload_wee_slack;
+pub use crate::weechat_process::{is_weechat_running, spawn_weechat_and_wait_ready};
+use crate::weechat_tokens::{get_token_values, remember_known_token};
+use anyhow::{bail, Context, Result};
 use log::{debug, info};
+use std::collections::HashSet;
+use std::io::{IsTerminal, Read, Write};
+use std::path::PathBuf;
 use std::process::exit;
-use sysinfo::{System, SystemExt};
+use std::time::{Duration, Instant};
+use sysinfo::System;
 use weechat_relay_rs::commands::{
     Command, InfolistCommand, InputCommand, PointerOrName, StrArgument,
 };
-use weechat_relay_rs::messages::{InfolistItem, Object, WInfolist};
+use weechat_relay_rs::messages::{Object, Pointer, WInfolist};
 use weechat_relay_rs::Connection;
 
-pub fn print_register_url() -> Result<()> {
+const REGISTER_TIMEOUT: Duration = Duration::from_secs(30);
+const REGISTER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const CORE_BUFFER_LINES: usize = 20;
+/* how wee-slack reports a failed /slack register in the core buffer */
+const REGISTER_ERRORS: [&str; 3] = [
+    "Couldn't add Slack team",
+    "Couldn't get Slack OAuth token",
+    "This token is already registered",
+];
+/* what slack answers wee-slack, and what to do about it */
+const SLACK_ERRORS: [(&str, &str); 6] = [
+    (
+        "invalid_code",
+        "The code expired, follow the link again ('slackrope register')",
+    ),
+    (
+        "code_already_used",
+        "The code was already used, follow the link again ('slackrope register')",
+    ),
+    (
+        "invalid_auth",
+        "The token is invalid (for an xoxc token, check its d cookie)",
+    ),
+    ("not_authed", "The token is missing or malformed"),
+    ("token_revoked", "The token was revoked, get a new one"),
+    (
+        "account_inactive",
+        "The account of this token is deactivated",
+    ),
+];
+
+pub struct RegisterFlags {
+    pub token: Option<String>,
+    pub token_stdin: bool,
+    pub token_file: Option<PathBuf>,
+}
+
+/*
+* Without a value, prints the slack url to follow,
+* then prompts for what slack redirected to (when stdin is a terminal)
+*/
+pub fn register(sys: &mut System, flags: RegisterFlags) -> Result<()> {
+    let value = match read_register_value(flags) {
+        Ok(Some(value)) => value,
+        Ok(None) => {
+            let interactive = std::io::stdin().is_terminal();
+            print_register_url(interactive);
+            match interactive {
+                true => prompt_register_value()?,
+                false => return Ok(()),
+            }
+        }
+        Err(error) => {
+            println!("Error : {}", redact_tokens(&format!("{error:#}")));
+            exit(1);
+        }
+    };
+    match normalize_register_value(&value) {
+        Ok(value) => register_slack_token(sys, &value),
+        Err(error) => {
+            println!("Error : {error}");
+            exit(1);
+        }
+    }
+}
+
+pub fn read_register_value(flags: RegisterFlags) -> Result<Option<String>> {
+    if flags.token_stdin {
+        let mut value = String::new();
+        std::io::stdin()
+            .read_to_string(&mut value)
+            .context("reading stdin")?;
+        return Ok(Some(value));
+    }
+    if let Some(path) = flags.token_file {
+        return read_private_file(&path).map(Some);
+    }
+    if let Some(token) = &flags.token {
+        if token.trim_start().starts_with("xox") {
+            println!("Warning : the token is visible in your shell history and to other users (ps), prefer --token-stdin or --token-file");
+        }
+    }
+    Ok(flags.token)
+}
+
+fn print_register_url(interactive: bool) {
     let slack_register_url = get_slack_register_url();
     println!("To register a new slack workspace, first you need a workspace token. Please follow this link:");
     println!();
     println!("  {slack_register_url}");
     println!();
+    if interactive {
+        println!("Then paste the address of the page slack redirects you to (or the code it shows, or a token).");
+        return;
+    }
     println!("Then you need to register your token. You can use the following command: ");
     println!();
-    println!("  register --token <TOKEN>");
-    Ok(())
+    println!("  register --token <REDIRECT_URL|CODE|TOKEN>");
+}
+
+pub fn prompt_register_value() -> Result<String> {
+    print!("> ");
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    if line.trim().is_empty() {
+        println!("Error : nothing to register");
+        exit(1);
+    }
+    Ok(line)
+}
+
+/*
+* What wee-slack's /slack register expects, from what users paste:
+* the redirect url (https://wee-slack.github.io/wee-slack/oauth?code=...&state=...),
+* the oauth code alone, or a token (xoxp-..., xoxc-... with its cookie)
+*/
+pub fn normalize_register_value(value: &str) -> Result<String> {
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+    if value.starts_with("xox") {
+        return normalize_token(value);
+    }
+    let code = match value.starts_with("http://") || value.starts_with("https://") {
+        true => {
+            let params = get_url_params(value);
+            if let Some((_, error)) = params.iter().find(|(name, _)| name == "error") {
+                bail!("slack refused the authorization ({error}), follow the link again");
+            }
+            match params.into_iter().find(|(name, _)| name == "code") {
+                Some((_, code)) => code,
+                None => bail!(
+                    "no code in this url, paste the address of the page slack redirects you to"
+                ),
+            }
+        }
+        false => value.to_string(),
+    };
+    let is_code = !code.is_empty()
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !is_code {
+        bail!("expected the redirect url, its code, or a token (xoxp-... or xoxc-...:xoxd-...)");
+    }
+    Ok(code)
+}
+
+/*
+* A session token (xoxc-...) only works with the browser's d cookie:
+* wee-slack expects "xoxc-...:xoxd-..." (or "xoxc-...:d=xoxd-...;d-s=..."),
+* which may also be pasted as the token and the cookie on two lines
+*/
+pub fn normalize_token(value: &str) -> Result<String> {
+    let token = match value.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [token] => token.to_string(),
+        [token, cookie] if !token.contains(':') => format!("{token}:{cookie}"),
+        _ => bail!("expected a token, or an xoxc token and its d cookie"),
+    };
+    if token.starts_with("xoxc-") && !token.contains(':') {
+        bail!("an xoxc token needs the d cookie of your browser session: xoxc-...:xoxd-...");
+    }
+    if token
+        .split_once(':')
+        .is_some_and(|(_, cookie)| cookie.is_empty())
+    {
+        bail!("the d cookie is empty");
+    }
+    Ok(token)
+}
+
+/*
+* The query (or fragment) parameters of a url, percent-decoded
+*/
+fn get_url_params(url: &str) -> Vec<(String, String)> {
+    let Some((_, query)) = url.split_once(['?', '#']) else {
+        return vec![];
+    };
+    query
+        .split(['&', '#'])
+        .filter_map(|param| param.split_once('='))
+        .map(|(name, value)| (percent_decode(name), percent_decode(value)))
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
 }
 
-pub fn register_slack_token(sys: &mut System, token: &String) -> Result<()> {
+fn register_slack_token(sys: &mut System, token: &str) -> Result<()> {
     if !is_weechat_running(sys) {
-        spawn_weechat_process()?;
-        sleep(2);
+        spawn_weechat_and_wait_ready()?;
     }
 
     let mut connection = init_connection()?;
@@ -44,7 +253,12 @@ pub fn register_slack_token(sys: &mut System, token: &String) -> Result<()> {
         (true, false) => {
             debug!("wee-slack python plugin is not loaded");
             println!("Error : wee-slack python plugin is not loaded in weechat.");
-            println!("Did you correctly install wee_slack.py python script ?");
+            let dirs = detect_weechat_dirs();
+            println!(
+                "Did you correctly install {} python script in {} ?",
+                config().wee_slack_plugin_filename,
+                get_wee_slack_plugin_directory(&dirs).display()
+            );
             exit(1);
         }
         _ => {
@@ -55,69 +269,184 @@ pub fn register_slack_token(sys: &mut System, token: &String) -> Result<()> {
         }
     };
 
+    let teams_before: Vec<String> = get_registered_slack_teams(&mut connection)?
+        .into_iter()
+        .map(|team| team.name)
+        .collect();
+    let tokens_before = get_token_values(&mut connection)?;
+    let mut core_buffer = CoreBufferWatch::new(&mut connection)?;
+
     println!("Registering slack token...");
     handle_register_request(&mut connection, token)?;
-    sleep(2);
-
-    println!("Reloading weechat...");
-    send_quit_command(&mut connection)?;
-    while is_weechat_running(sys) {
-        sys.refresh_processes();
-        debug!("waiting for weechat to quit...");
-        sleep(1);
+    // at once for a token, once slack answered for a code
+    let deadline = Instant::now() + REGISTER_TIMEOUT;
+    let added_team = loop {
+        let lines = core_buffer.new_lines(&mut connection)?;
+        exit_on_register_error(&lines);
+        if let Some(line) = lines
+            .iter()
+            .find(|line| line.message.starts_with("Success!"))
+        {
+            break get_added_team(&line.message);
+        }
+        if Instant::now() > deadline {
+            println!(
+                "Error : wee-slack did not confirm the registration, see weechat's core buffer"
+            );
+            exit(1);
+        }
+        std::thread::sleep(REGISTER_POLL_INTERVAL);
+    };
+
+    /*
+     * wee-slack only connects the teams of its tokens when it loads.
+     * The reload closes the team buffers, so the added team's buffer shows up
+     * once it connected, whether it existed before or not (a team registered again)
+     */
+    println!("Reloading wee-slack...");
+    if let Err(error) = reload_wee_slack(&mut connection) {
+        println!("Error : {error:#}");
+        println!(
+            "The token is saved in wee-slack's option, run 'slackrope plugin reload' once fixed"
+        );
+        exit(1);
     }
+    let deadline = Instant::now() + REGISTER_TIMEOUT;
+    let team = loop {
+        let teams = get_registered_slack_teams(&mut connection)?;
+        // else a team which had no buffer (an older wee-slack does not name the team it added)
+        let team = teams
+            .iter()
+            .find(|team| {
+                added_team
+                    .as_deref()
+                    .is_some_and(|added_team| is_team_buffer(&team.name, added_team))
+            })
+            .or_else(|| teams.iter().find(|team| !teams_before.contains(&team.name)));
+        if let Some(team) = team {
+            break team.name.clone();
+        }
+        if Instant::now() > deadline {
+            println!(
+                "Error : wee-slack did not connect {}, see weechat's core buffer",
+                added_team.as_deref().unwrap_or("the new team")
+            );
+            exit(1);
+        }
+        std::thread::sleep(REGISTER_POLL_INTERVAL);
+    };
 
-    // restart
-    sleep(2);
-    spawn_weechat_process()?;
-    sleep(2);
+    let new_tokens: Vec<String> = get_token_values(&mut connection)?
+        .into_iter()
+        .filter(|value| !tokens_before.contains(value))
+        .collect();
+    if let [token] = new_tokens.as_slice() {
+        remember_known_token(&team, token)?;
+    }
+    println!("Registered {team}, see 'slackrope list-teams'");
+    Ok(())
+}
 
-    let mut connection = init_connection()?;
-    debug!("connection initiated");
-    send_infolist_buffer_request(&mut connection)?;
-    let infolist = get_infolist_buffer_response(&mut connection)?;
-    debug!("successfully got infolist response");
+/*
+* e.g 'Success! Added team "workspace"' => "workspace"
+*/
+fn get_added_team(message: &str) -> Option<String> {
+    let (_, rest) = message.split_once('"')?;
+    let (team, _) = rest.rsplit_once('"')?;
+    Some(team.to_string())
+}
 
-    let teams = build_slack_registered_teams(&infolist)?;
+/*
+* wee-slack names a team buffer "slack.<team>"
+*/
+fn is_team_buffer(buffer_name: &str, team: &str) -> bool {
+    buffer_name
+        .strip_prefix("slack.")
+        .unwrap_or(buffer_name)
+        .eq_ignore_ascii_case(team)
+}
 
-    if teams.len() != 0 {
-        println!();
-        println!(
-            "You have currently {} registered slack team(s) a.k.a workspace(s) :",
-            teams.len()
-        );
-        for team in teams {
-            println!("  - {}", team.name)
-        }
-        println!();
-        println!("Note that, in case you don't see the slack team (aka workspace) you just tried to register :");
-        println!();
-        print_anomaly_advice();
-    } else {
-        println!();
-        println!("Weechat currently couldn't find any registered slack team (aka workspace) :");
-        println!();
-        print_anomaly_advice();
+/*
+* The lines wee-slack prints in the core buffer from now on
+*/
+struct CoreBufferWatch {
+    pointer: Pointer,
+    seen: HashSet<(u64, String, String)>,
+}
+
+impl CoreBufferWatch {
+    fn new(connection: &mut Connection) -> Result<CoreBufferWatch> {
+        let Some(core_buffer) = get_buffers(connection, Some("core.weechat"))?
+            .into_iter()
+            .next()
+        else {
+            bail!("weechat's core buffer not found");
+        };
+        let mut watch = CoreBufferWatch {
+            pointer: core_buffer.pointer,
+            seen: HashSet::new(),
+        };
+        watch.new_lines(connection)?;
+        Ok(watch)
     }
 
-    Ok(())
+    fn new_lines(&mut self, connection: &mut Connection) -> Result<Vec<BufferLine>> {
+        send_buffer_lines_request(connection, &self.pointer, CORE_BUFFER_LINES)?;
+        let lines = get_buffer_lines_response(connection, CORE_BUFFER_LINES)?;
+        Ok(lines
+            .into_iter()
+            .filter(|line| {
+                self.seen
+                    .insert((line.date, line.prefix.clone(), line.message.clone()))
+            })
+            .collect())
+    }
+}
+
+/*
+* wee-slack reports a failed registration in the core buffer, e.g
+* "ERROR: Couldn't get Slack OAuth token: invalid_code".
+* Other lines (another team's error, another script's) are not about this registration
+*/
+fn exit_on_register_error(lines: &[BufferLine]) {
+    let Some(line) = lines.iter().find(|line| {
+        REGISTER_ERRORS
+            .iter()
+            .any(|error| line.message.contains(error))
+    }) else {
+        return;
+    };
+    println!("Error : wee-slack: {}", redact_tokens(&line.message));
+    if let Some((_, hint)) = SLACK_ERRORS
+        .iter()
+        .find(|(code, _)| line.message.contains(code))
+    {
+        println!("{hint}");
+    }
+    exit(1);
+}
+
+/*
+* wee-slack's team (server) buffers, see BufferInfo::is_slack_team
+*/
+pub fn get_registered_slack_teams(connection: &mut Connection) -> Result<Vec<SlackTeam>> {
+    Ok(get_buffers(connection, Some("python.*"))?
+        .into_iter()
+        .filter(|buffer| buffer.is_slack_team())
+        .map(|buffer| SlackTeam { name: buffer.name })
+        .collect())
 }
 
 pub fn list_registered_slack_teams(sys: &mut System) -> Result<()> {
     if !is_weechat_running(sys) {
-        spawn_weechat_process()?;
-        sleep(2);
+        spawn_weechat_and_wait_ready()?;
     }
 
     let mut connection = init_connection()?;
     debug!("connection initiated");
 
-    send_infolist_buffer_request(&mut connection)?;
-    let infolist = get_infolist_buffer_response(&mut connection)?;
-    debug!("successfully got infolist response");
-
-    let teams = build_slack_registered_teams(&infolist)?;
-    if teams.len() != 0 {
+    let teams = get_registered_slack_teams(&mut connection)?;
+    if !teams.is_empty() {
         println!();
         println!(
             "You have currently {} registered slack team(s) a.k.a workspace(s) :",
@@ -133,18 +462,7 @@ pub fn list_registered_slack_teams(sys: &mut System) -> Result<()> {
     Ok(())
 }
 
-fn print_anomaly_advice() {
-    println!("  - Either your newly registered slack team isn't loaded in Weechat yet");
-    println!("      > You may check again later, using the 'list-slack-teams' command.");
-    println!();
-    println!("  - Or your token was invalid");
-    println!("      > You may retry the whole process, ");
-    println!(
-        "      > ensuring that you use a valid token in the 'register --token <TOKEN>' command."
-    );
-}
-
-fn handle_register_request(connection: &mut Connection, token: &String) -> Result<()> {
+fn handle_register_request(connection: &mut Connection, token: &str) -> Result<()> {
     connection.send_command(&build_input_command(
         "core.weechat",
         format!("/slack register {}", token).as_str(),
@@ -155,7 +473,7 @@ fn handle_register_request(connection: &mut Connection, token: &String) -> Resul
     Ok(())
 }
 
-fn send_quit_command(connection: &mut Connection) -> Result<()> {
+pub fn send_quit_command(connection: &mut Connection) -> Result<()> {
     connection.send_command(&build_input_command("core.weechat", "/quit"))?;
 
     debug!("successfully sent /quit command");
@@ -163,7 +481,7 @@ fn send_quit_command(connection: &mut Connection) -> Result<()> {
     Ok(())
 }
 
-fn build_input_command(buffer_name: &str, command: &str) -> Command<InputCommand> {
+pub fn build_input_command(buffer_name: &str, command: &str) -> Command<InputCommand> {
     let input_command = InputCommand::new(
         PointerOrName::Name(StrArgument::new(buffer_name).unwrap().to_stringargument()),
         StrArgument::new(command).unwrap().to_stringargument(),
@@ -174,84 +492,6 @@ fn build_input_command(buffer_name: &str, command: &str) -> Command<InputCommand
     }
 }
 
-fn send_infolist_buffer_request(connection: &mut Connection) -> Result<()> {
-    let info_command = InfolistCommand::new(
-        StrArgument::new("buffer").unwrap().to_stringargument(),
-        None,
-        vec![StrArgument::new("python.slack.*")
-            .unwrap()
-            .to_stringargument()],
-    );
-    let info_command: Command<InfolistCommand> = Command {
-        id: None,
-        command: info_command,
-    };
-    connection.send_command(&info_command)?;
-    Ok(())
-}
-
-fn get_infolist_buffer_response(connection: &mut Connection) -> Result<Option<WInfolist>> {
-    let message = connection.get_message();
-    match message {
-        Ok(m) => {
-            if m.objects.len() > 0 {
-                match &m.objects[0] {
-                    Object::Inl(infolist) => Ok(Some(infolist.clone())),
-                    _ => {
-                        info!("Could not parse the hotlist response");
-                        Ok(None)
-                    }
-                }
-            } else {
-                Ok(None)
-            }
-        }
-        Err(e) => {
-            debug!("{:?}", e);
-            info!("Could not receive the hotlist response");
-            Ok(None)
-        }
-    }
-}
-
-fn build_slack_registered_teams(infolist: &Option<WInfolist>) -> Result<Vec<SlackTeam>> {
-    match infolist {
-        Some(infolist) => {
-            let mut teams: Vec<SlackTeam> = vec![];
-            for item in infolist.items.iter() {
-                for variable in item.variables.iter() {
-                    if match_string(&variable.name, "localvar_value_00008") {
-                        if let Some(buffer_type) = match &variable.value {
-                            Object::Str(s) => Some(clean_string(&s)),
-                            _ => None,
-                        } {
-                            match buffer_type.as_str() {
-                                "team" => count_slack_team(&item, &mut teams),
-                                _ => (),
-                            }
-                        }
-                    }
-                }
-            }
-            Ok(teams)
-        }
-        None => Ok(vec![]),
-    }
-}
-
-fn count_slack_team(item: &InfolistItem, teams: &mut Vec<SlackTeam>) {
-    for variable in item.variables.iter() {
-        if match_string(&variable.name, "name") {
-            if let Some(buffer_name) = match &variable.value {
-                Object::Str(buffer_name) => Some(clean_string(&buffer_name)),
-                _ => None,
-            } {
-                teams.push(SlackTeam { name: buffer_name })
-            }
-        }
-    }
-}
-
 fn send_infolist_python_script_request(connection: &mut Connection) -> Result<()> {
     let info_command = InfolistCommand::new(
         StrArgument::new("python_script")
@@ -272,7 +512,7 @@ fn get_infolist_python_script_response(connection: &mut Connection) -> Result<Op
     let message = connection.get_message();
     match message {
         Ok(m) => {
-            if m.objects.len() > 0 {
+            if !m.objects.is_empty() {
                 match &m.objects[0] {
                     Object::Inl(infolist) => Ok(Some(infolist.clone())),
                     _ => {
@@ -292,6 +532,31 @@ fn get_infolist_python_script_response(connection: &mut Connection) -> Result<Op
     }
 }
 
+/*
+* The version of the loaded wee-slack script, none when it is not loaded
+*/
+pub fn get_wee_slack_plugin_version(connection: &mut Connection) -> Result<Option<String>> {
+    send_infolist_python_script_request(connection)?;
+    let infolist = get_infolist_python_script_response(connection)?;
+    for item in infolist.iter().flat_map(|infolist| infolist.items.iter()) {
+        let mut name = None;
+        let mut version = None;
+        for variable in item.variables.iter() {
+            if let Object::Str(s) = &variable.value {
+                if match_string(&variable.name, "name") {
+                    name = Some(clean_string(s));
+                } else if match_string(&variable.name, "version") {
+                    version = Some(clean_string(s));
+                }
+            }
+        }
+        if name.as_deref() == Some("slack") {
+            return Ok(Some(version.unwrap_or_default()));
+        }
+    }
+    Ok(None)
+}
+
 pub fn check_connection_and_python_wee_slack_plugin(
     connection: &mut Connection,
 ) -> Result<(bool, bool)> {
@@ -306,12 +571,11 @@ pub fn check_connection_and_python_wee_slack_plugin(
                 for variable in item.variables.iter() {
                     if match_string(&variable.name, "name") {
                         if let Some(name) = match &variable.value {
-                            Object::Str(s) => Some(clean_string(&s)),
+                            Object::Str(s) => Some(clean_string(s)),
                             _ => None,
                         } {
-                            match name.as_str() {
-                                "slack" => is_python_wee_slack_installed = true,
-                                _ => (),
+                            if name.as_str() == "slack" {
+                                is_python_wee_slack_installed = true
                             }
                         }
                     }
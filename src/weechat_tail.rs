@@ -0,0 +1,165 @@
+pub use crate::cli::TailFormat;
+pub use crate::models::Line;
+use crate::utils::format_local_time;
+pub use crate::utils::get_buffer_workspace;
+pub use crate::weechat_buffers::{
+    build_line_data, get_buffer_lines_response, get_buffers, send_buffer_lines_request, BufferInfo,
+    BufferLine,
+};
+pub use crate::weechat_connection::init_connection;
+pub use crate::weechat_process::is_weechat_running;
+use anyhow::Result;
+use log::debug;
+use std::collections::HashSet;
+use std::process::exit;
+use sysinfo::System;
+use weechat_relay_rs::commands::{Command, PointerOrName, SyncCommand, SyncSomeBuffers};
+use weechat_relay_rs::messages::{Event, Identifier, Object};
+
+pub struct TailFlags {
+    pub buffers: Vec<String>,
+    pub workspaces: Vec<String>,
+    pub lines: usize,
+    pub follow: bool,
+    pub format: TailFormat,
+}
+
+pub fn tail(sys: &mut System, flags: TailFlags) -> Result<()> {
+    if !is_weechat_running(sys) {
+        println!("Weechat is currently not running.");
+        exit(1);
+    }
+
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+
+    let buffers = get_buffers(&mut connection, None)?;
+    let mut followed: Vec<BufferInfo> = vec![];
+    for name in flags.buffers.iter() {
+        match buffers.iter().find(|b| b.matches(name)) {
+            Some(buffer) => followed.push(buffer.clone()),
+            None => {
+                println!("Error : could not find buffer {name}");
+                exit(1);
+            }
+        }
+    }
+    for workspace in flags.workspaces.iter() {
+        let workspace_buffers: Vec<&BufferInfo> = buffers
+            .iter()
+            .filter(|b| {
+                b.full_name.starts_with("python.")
+                    && !b.is_slack_team()
+                    && get_buffer_workspace(&b.name) == workspace
+            })
+            .collect();
+        if workspace_buffers.is_empty() {
+            println!("Error : could not find any buffer for workspace {workspace}");
+            exit(1);
+        }
+        followed.extend(workspace_buffers.into_iter().cloned());
+    }
+    // a buffer both named and part of a followed workspace is printed once
+    let mut seen = HashSet::new();
+    followed.retain(|b| seen.insert(b.full_name.clone()));
+    let with_buffer_name = followed.len() > 1;
+
+    if flags.lines > 0 {
+        for buffer in followed.iter() {
+            send_buffer_lines_request(&mut connection, &buffer.pointer, flags.lines)?;
+            for line in get_buffer_lines_response(&mut connection, flags.lines)? {
+                print_line(&buffer.name, &line, with_buffer_name, flags.format);
+            }
+        }
+    }
+
+    if !flags.follow {
+        return Ok(());
+    }
+
+    connection.send_command(&Command {
+        id: None,
+        command: SyncCommand::SomeBuffers(
+            followed
+                .iter()
+                .map(|b| PointerOrName::Pointer(b.pointer.clone()))
+                .collect(),
+            SyncSomeBuffers::Buffer,
+        ),
+    })?;
+    debug!("sync request sent");
+
+    loop {
+        let message = match connection.get_message() {
+            Ok(message) => message,
+            Err(e) => {
+                debug!("{:?}", e);
+                println!("Error : the connection to weechat was lost");
+                exit(1);
+            }
+        };
+        if let (Identifier::Event(Event::BufferLineAdded), Some(Object::Hda(hdata))) =
+            (&message.id, message.objects.first())
+        {
+            for line in build_line_data(hdata) {
+                if let Some(buffer) = followed
+                    .iter()
+                    .find(|b| Some(&b.pointer) == line.buffer_pointer.as_ref())
+                {
+                    print_line(&buffer.name, &line, with_buffer_name, flags.format);
+                }
+            }
+        }
+    }
+}
+
+fn print_line(buffer_name: &str, line: &BufferLine, with_buffer_name: bool, format: TailFormat) {
+    match format {
+        TailFormat::Plain => {
+            let buffer = if with_buffer_name {
+                format!("[{buffer_name}] ")
+            } else {
+                String::new()
+            };
+            println!(
+                "{buffer}{} {} | {}",
+                format_local_time(line.date, false),
+                line.prefix,
+                line.message
+            )
+        }
+        TailFormat::Color => {
+            let buffer = if with_buffer_name {
+                format!("\x1b[38;5;110m[{buffer_name}]\x1b[0m ")
+            } else {
+                String::new()
+            };
+            println!(
+                "{buffer}\x1b[2m{}\x1b[0m \x1b[38;5;{}m{}\x1b[0m | {}",
+                format_local_time(line.date, false),
+                nick_color(&line.prefix),
+                line.prefix,
+                line.message
+            )
+        }
+        TailFormat::Json => {
+            let serialized = serde_json::to_string(&Line {
+                buffer: buffer_name.to_string(),
+                date: line.date,
+                prefix: line.prefix.clone(),
+                message: line.message.clone(),
+            })
+            .unwrap();
+            println!("{}", serialized);
+        }
+    }
+}
+
+/* A stable color per nick, like weechat does */
+fn nick_color(nick: &str) -> u8 {
+    const COLORS: [u8; 8] = [33, 37, 71, 107, 136, 167, 170, 208];
+    let hash = nick
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    COLORS[hash as usize % COLORS.len()]
+}
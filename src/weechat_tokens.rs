@@ -0,0 +1,522 @@
+pub use crate::cli::TokensAction;
+pub use crate::environment::{config, is_remote_mode};
+pub use crate::models::KnownToken;
+use crate::password::{get_sec_data_name, read_sec_data};
+pub use crate::state::{read_state, write_state};
+use crate::utils::{clean_string, match_string};
+use crate::weechat_buffers::ask_core_buffer;
+use crate::weechat_conf::{get_weechat_conf_path, set_sec_data, ConfValue, WeechatConf};
+use crate::weechat_connection::init_connection;
+use crate::weechat_plugin::reload_wee_slack;
+use crate::weechat_process::is_weechat_running;
+use crate::weechat_slack::{
+    build_input_command, get_registered_slack_teams, normalize_token, prompt_register_value,
+    read_register_value, RegisterFlags,
+};
+use anyhow::{bail, Result};
+use log::debug;
+use sha2::{Digest, Sha256};
+use std::io::IsTerminal;
+use std::process::exit;
+use std::time::Duration;
+use sysinfo::System;
+use weechat_relay_rs::commands::{Command, InfolistCommand, StrArgument};
+use weechat_relay_rs::messages::Object;
+use weechat_relay_rs::Connection;
+
+/* the comma-separated tokens of wee-slack, one per team */
+const TOKEN_OPTION: &str = "plugins.var.python.slack.slack_api_token";
+/* its name in plugins.conf, section [var] */
+const TOKEN_CONF_OPTION: &str = "python.slack.slack_api_token";
+/* which team each token connects, as slackrope learnt it */
+const KNOWN_TOKENS_FILE: &str = "slack_tokens.json";
+/* the core buffer local variable weechat writes a secured data value to */
+const SEC_DATA_LOCALVAR: &str = "slackrope_sec_data";
+const SEC_DATA_TIMEOUT: Duration = Duration::from_secs(3);
+
+/*
+* Where the tokens are read and written:
+* through the relay while weechat runs (it would overwrite plugins.conf when quitting),
+* else in plugins.conf
+*/
+enum TokenStore {
+    Relay(Connection),
+    Conf(WeechatConf),
+}
+
+/*
+* A token of wee-slack's option, and the team it connects when known.
+* probable_team: the team it likely connects, when it is the only token and team left unknown.
+* sec_data: the secured data holding it, when the option refers to one
+* (e.g "${sec.data.slack_token}", which may hold several comma-separated tokens)
+*/
+#[derive(Clone)]
+struct TokenEntry {
+    value: String,
+    team: Option<String>,
+    probable_team: Option<String>,
+    sec_data: Option<String>,
+}
+
+pub fn tokens_command(
+    sys: &mut System,
+    action: TokensAction,
+    team: Option<String>,
+    flags: RegisterFlags,
+) -> Result<()> {
+    let mut store = open_token_store(sys)?;
+    match action {
+        TokensAction::List => list_tokens(&mut store),
+        TokensAction::Remove => remove_token(&mut store, &team.unwrap_or_default()),
+        TokensAction::Rotate => rotate_token(&mut store, &team.unwrap_or_default(), flags),
+    }
+}
+
+fn list_tokens(store: &mut TokenStore) -> Result<()> {
+    let entries = read_token_entries(store)?;
+    if entries.is_empty() {
+        println!("No slack token, see 'slackrope register'");
+        return Ok(());
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        println!(
+            "{}. {} {}{}",
+            i + 1,
+            match (&entry.team, &entry.probable_team) {
+                (Some(team), _) => team.to_string(),
+                (None, Some(team)) => format!("(probably {team})"),
+                (None, None) => "(unknown team)".to_string(),
+            },
+            mask_token(&entry.value),
+            match &entry.sec_data {
+                Some(name) => format!(" (in ${{sec.data.{name}}})"),
+                None => String::new(),
+            }
+        );
+    }
+    if entries.iter().any(|entry| entry.team.is_none()) {
+        println!();
+        println!("The team of a token is learnt when it registers, use the token number meanwhile");
+    }
+    Ok(())
+}
+
+fn remove_token(store: &mut TokenStore, team: &str) -> Result<()> {
+    let before = read_token_entries(store)?;
+    let i = find_token_entry(&before, team);
+    let mut entries = before.clone();
+    let removed = entries.remove(i);
+    write_token_entries(store, &before, &entries)?;
+    forget_known_token(&removed.value)?;
+    println!(
+        "Removed the token {}{}",
+        mask_token(&removed.value),
+        describe_team(&removed.team)
+    );
+    reload_after_change(store)
+}
+
+/*
+* A token stored in weechat's secured data is replaced there,
+* and the option keeps referring to it
+*/
+fn rotate_token(store: &mut TokenStore, team: &str, flags: RegisterFlags) -> Result<()> {
+    let before = read_token_entries(store)?;
+    let i = find_token_entry(&before, team);
+    let value = match read_register_value(flags)? {
+        Some(value) => value,
+        None if std::io::stdin().is_terminal() => {
+            println!("Paste the new token (xoxp-..., or xoxc-...:xoxd-...)");
+            prompt_register_value()?
+        }
+        None => {
+            println!("Error : no new token, use --token-stdin or --token-file");
+            exit(1);
+        }
+    };
+    let token = match value.trim().starts_with("xox") {
+        true => normalize_token(value.trim()),
+        false => Err(anyhow::anyhow!(
+            "expected a token (xoxp-... or xoxc-...:xoxd-...)"
+        )),
+    };
+    let token = match token {
+        Ok(token) => token,
+        Err(error) => {
+            println!("Error : {error}");
+            exit(1);
+        }
+    };
+
+    let mut entries = before.clone();
+    entries[i].value = token.clone();
+    write_token_entries(store, &before, &entries)?;
+    if let Some(team) = &entries[i].team {
+        forget_known_token(&before[i].value)?;
+        remember_known_token(team, &token)?;
+    }
+    println!(
+        "Replaced the token {}{} with {}",
+        i + 1,
+        describe_team(&entries[i].team),
+        mask_token(&token)
+    );
+    reload_after_change(store)
+}
+
+fn describe_team(team: &Option<String>) -> String {
+    match team {
+        Some(team) => format!(" of {team}"),
+        None => String::new(),
+    }
+}
+
+fn open_token_store(sys: &mut System) -> Result<TokenStore> {
+    if is_weechat_running(sys) {
+        return Ok(TokenStore::Relay(init_connection()?));
+    }
+    if is_remote_mode() {
+        println!(
+            "Error : the weechat relay is not reachable on {}:{}",
+            config().weechat_host,
+            config().weechat_relay_port
+        );
+        exit(1);
+    }
+    let path = get_weechat_conf_path(config(), "plugins.conf");
+    Ok(TokenStore::Conf(WeechatConf::read(&path)?))
+}
+
+/*
+* A team name, or a token number as 'tokens list' prints it
+*/
+fn find_token_entry(entries: &[TokenEntry], team: &str) -> usize {
+    let by_number = team
+        .parse::<usize>()
+        .ok()
+        .filter(|n| (1..=entries.len()).contains(n))
+        .map(|n| n - 1);
+    let by_team = || {
+        entries
+            .iter()
+            .position(|entry| entry.team.as_deref() == Some(team))
+    };
+    match by_number.or_else(by_team) {
+        Some(i) => i,
+        None => {
+            println!(
+                "Error : no known token for {team}, use its number from 'slackrope tokens list'"
+            );
+            exit(1);
+        }
+    }
+}
+
+/*
+* The tokens of the option, the secured data it refers to split as well,
+* each with its team when slackrope learnt it at register time:
+* a token whose team is the only one left unknown probably connects that team
+*/
+fn read_token_entries(store: &mut TokenStore) -> Result<Vec<TokenEntry>> {
+    let values = match store {
+        TokenStore::Relay(connection) => get_token_values(connection)?,
+        TokenStore::Conf(conf) => split_tokens(&conf.get("var", TOKEN_CONF_OPTION)),
+    };
+    let known_tokens: Vec<KnownToken> = read_state(KNOWN_TOKENS_FILE)?.unwrap_or_default();
+    let mut entries: Vec<TokenEntry> = vec![];
+    for value in values {
+        let (tokens, sec_data) = match get_sec_data_name(&value) {
+            Some(name) => (
+                split_tokens(&Some(read_secured_tokens(store, name)?)),
+                Some(name.to_string()),
+            ),
+            None => (vec![value], None),
+        };
+        entries.extend(tokens.into_iter().map(|value| {
+            TokenEntry {
+                team: known_tokens
+                    .iter()
+                    .find(|known| known.fingerprint == fingerprint_token(&value))
+                    .map(|known| known.team.clone()),
+                probable_team: None,
+                value,
+                sec_data: sec_data.clone(),
+            }
+        }));
+    }
+
+    if let TokenStore::Relay(connection) = store {
+        let teams = get_registered_slack_teams(connection)?;
+        let unknown_teams: Vec<&String> = teams
+            .iter()
+            .map(|team| &team.name)
+            .filter(|name| entries.iter().all(|e| e.team.as_ref() != Some(name)))
+            .collect();
+        let unknown_entries: Vec<usize> = (0..entries.len())
+            .filter(|i| entries[*i].team.is_none())
+            .collect();
+        if let ([team], [i]) = (unknown_teams.as_slice(), unknown_entries.as_slice()) {
+            debug!("token {} probably connects {team}", i + 1);
+            entries[*i].probable_team = Some(team.to_string());
+        }
+    }
+    Ok(entries)
+}
+
+/*
+* The value of a ${sec.data.NAME} token: read in sec.conf when it is local and unencrypted,
+* else asked to weechat while it runs.
+* Refused when it cannot be read, editing it blindly would drop the other teams' tokens
+*/
+fn read_secured_tokens(store: &mut TokenStore, name: &str) -> Result<String> {
+    let local = match is_remote_mode() {
+        true => None,
+        false => read_sec_data(config(), name)
+            .map_err(|error| debug!("{:?}", error))
+            .ok(),
+    };
+    let value = match (local, store) {
+        (Some(value), _) => Some(value),
+        /*
+        * The tradeoff: weechat resolves the secured data into a core buffer local variable,
+        * readable by the other relay clients and scripts until slackrope deletes it.
+        * Accepted: a relay client can already run /secure and /eval itself
+        */
+        (None, TokenStore::Relay(connection)) => ask_core_buffer(
+            connection,
+            SEC_DATA_LOCALVAR,
+            |nonce| {
+                format!("/mute /eval /buffer set localvar_set_{SEC_DATA_LOCALVAR} {nonce} ${{sec.data.{name}}}")
+            },
+            SEC_DATA_TIMEOUT,
+        )?
+        .filter(|value| !value.is_empty()),
+        (None, TokenStore::Conf(_)) => match read_sec_data(config(), name) {
+            Ok(value) => Some(value),
+            Err(error) => {
+                println!("Error : cannot read ${{sec.data.{name}}}, {error:#}");
+                exit(1);
+            }
+        },
+    };
+    match value {
+        Some(value) => Ok(value),
+        None => {
+            println!(
+                "Error : weechat did not resolve ${{sec.data.{name}}}, is sec.conf encrypted and locked ?"
+            );
+            exit(1);
+        }
+    }
+}
+
+/*
+* Writes what changed between before and after: the option (/mute: weechat would print
+* the new value in its core buffer), and the secured data holding the tokens.
+* A secured data left without token is no longer referred to, but kept in weechat
+*/
+fn write_token_entries(
+    store: &mut TokenStore,
+    before: &[TokenEntry],
+    after: &[TokenEntry],
+) -> Result<()> {
+    let option = build_token_option(after);
+    if option != build_token_option(before) {
+        write_token_option(store, &option)?;
+    }
+    let mut names: Vec<&String> = before.iter().filter_map(|e| e.sec_data.as_ref()).collect();
+    names.sort();
+    names.dedup();
+    for name in names {
+        let joined = |entries: &[TokenEntry]| {
+            entries
+                .iter()
+                .filter(|e| e.sec_data.as_ref() == Some(name))
+                .map(|e| e.value.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let value = joined(after);
+        if value.is_empty() {
+            println!("${{sec.data.{name}}} holds no token anymore, see '/secure del {name}'");
+        } else if value != joined(before) {
+            write_sec_data(store, name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/*
+* The option value: the tokens, and a reference to each secured data holding some
+*/
+fn build_token_option(entries: &[TokenEntry]) -> String {
+    let mut values: Vec<String> = vec![];
+    for entry in entries {
+        let value = match &entry.sec_data {
+            Some(name) => format!("${{sec.data.{name}}}"),
+            None => entry.value.clone(),
+        };
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+    values.join(",")
+}
+
+fn write_token_option(store: &mut TokenStore, value: &str) -> Result<()> {
+    match store {
+        TokenStore::Relay(connection) => {
+            let command = format!("/mute /set {TOKEN_OPTION} \"{value}\"");
+            connection.send_command(&build_input_command("core.weechat", &command))?;
+        }
+        TokenStore::Conf(conf) => {
+            conf.set(
+                "var",
+                TOKEN_CONF_OPTION,
+                &ConfValue::String(value.to_string()),
+            );
+            conf.save()?;
+            println!("Wrote {}", conf.path.display());
+        }
+    }
+    Ok(())
+}
+
+fn write_sec_data(store: &mut TokenStore, name: &str, value: &str) -> Result<()> {
+    match store {
+        TokenStore::Relay(connection) => {
+            let command = format!("/mute /secure set {name} {value}");
+            connection.send_command(&build_input_command("core.weechat", &command))?;
+            /* sec.conf is read first by read_secured_tokens, keep it current */
+            connection.send_command(&build_input_command("core.weechat", "/mute /save sec"))?;
+        }
+        TokenStore::Conf(_) => match set_sec_data(config(), name, value) {
+            Ok(path) => println!("Wrote {}", path.display()),
+            Err(error) => {
+                println!("Error : {error:#}, start weechat to edit the token");
+                exit(1);
+            }
+        },
+    }
+    Ok(())
+}
+
+/*
+* wee-slack only reads its tokens when it loads
+*/
+fn reload_after_change(store: &mut TokenStore) -> Result<()> {
+    match store {
+        TokenStore::Relay(connection) => {
+            println!("Reloading wee-slack...");
+            match reload_wee_slack(connection) {
+                Ok(version) => println!("wee-slack {version} is reloaded"),
+                Err(error) => {
+                    println!("Error : {error:#}");
+                    println!("The tokens are saved, run 'slackrope plugin reload' once fixed");
+                    exit(1);
+                }
+            }
+        }
+        TokenStore::Conf(_) => println!("wee-slack will use it when weechat starts"),
+    }
+    Ok(())
+}
+
+/*
+* The tokens of the running wee-slack, as its option lists them
+*/
+pub fn get_token_values(connection: &mut Connection) -> Result<Vec<String>> {
+    Ok(split_tokens(&get_option_value(connection, TOKEN_OPTION)?))
+}
+
+fn split_tokens(option: &Option<String>) -> Vec<String> {
+    option
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+fn get_option_value(connection: &mut Connection, name: &str) -> Result<Option<String>> {
+    connection.send_command(&Command {
+        id: None,
+        command: InfolistCommand::new(
+            StrArgument::new("option").unwrap().to_stringargument(),
+            None,
+            vec![StrArgument::new(name).unwrap().to_stringargument()],
+        ),
+    })?;
+    let message = match connection.get_message() {
+        Ok(message) => message,
+        Err(e) => {
+            debug!("{:?}", e);
+            bail!("could not read the {name} option");
+        }
+    };
+    let Some(Object::Inl(infolist)) = message.objects.first() else {
+        return Ok(None);
+    };
+    for item in infolist.items.iter() {
+        for variable in item.variables.iter() {
+            if let Object::Str(value) = &variable.value {
+                if match_string(&variable.name, "value") {
+                    return Ok(Some(clean_string(value)));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/*
+* For display only, e.g "xoxp-****wxyz" or "xoxc-****abcd:****"
+*/
+fn mask_token(value: &str) -> String {
+    let (token, cookie) = match value.split_once(':') {
+        Some((token, _)) => (token, ":****"),
+        None => (value, ""),
+    };
+    let prefix: String = token.chars().take(5).collect();
+    let suffix: String = token
+        .chars()
+        .skip(5)
+        .collect::<Vec<_>>()
+        .iter()
+        .rev()
+        .take(4)
+        .rev()
+        .collect();
+    format!("{prefix}****{suffix}{cookie}")
+}
+
+/*
+* Identifies a token in slack_tokens.json: the first 16 hex digits of its sha256
+*/
+fn fingerprint_token(value: &str) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .take(8)
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+pub fn remember_known_token(team: &str, value: &str) -> Result<()> {
+    let fingerprint = fingerprint_token(value);
+    let mut known_tokens: Vec<KnownToken> = read_state(KNOWN_TOKENS_FILE)?.unwrap_or_default();
+    known_tokens.retain(|known| known.team != team && known.fingerprint != fingerprint);
+    known_tokens.push(KnownToken {
+        team: team.to_string(),
+        fingerprint,
+    });
+    write_state(KNOWN_TOKENS_FILE, &known_tokens)
+}
+
+fn forget_known_token(value: &str) -> Result<()> {
+    let fingerprint = fingerprint_token(value);
+    let mut known_tokens: Vec<KnownToken> = read_state(KNOWN_TOKENS_FILE)?.unwrap_or_default();
+    known_tokens.retain(|known| known.fingerprint != fingerprint);
+    write_state(KNOWN_TOKENS_FILE, &known_tokens)
+}
@@ -0,0 +1,455 @@
+pub use crate::mutes::get_active_mutes;
+pub use crate::utils::{format_local_time, get_buffer_workspace};
+pub use crate::weechat_buffers::{
+    build_buffer_input_command, build_buffer_lines, build_buffer_lines_command, build_line_data,
+    send_mark_buffer_read, BufferLine,
+};
+pub use crate::weechat_connection::init_connection;
+pub use crate::weechat_hotlist::{
+    build_hotlist_command, build_hotlist_entries, order_hotlist_entries, HotlistEntry,
+};
+pub use crate::weechat_process::is_weechat_running;
+use anyhow::Result;
+use crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::{cursor, execute, queue, terminal};
+use log::debug;
+use std::io::{stdout, Stdout, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+use sysinfo::System;
+use weechat_relay_rs::commands::{Command, QuitCommand, SyncAllBuffers, SyncCommand};
+use weechat_relay_rs::messages::{Event, Identifier, Message, Object, Pointer};
+use weechat_relay_rs::Connection;
+
+const HOTLIST_ID: &str = "hotlist";
+const LINES_ID: &str = "lines";
+// the hotlist also changes without new lines (e.g read from another client)
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+pub struct UiFlags {
+    pub lines: usize,
+}
+
+enum RelayEvent {
+    Message(Message),
+    Disconnected,
+}
+
+enum Row {
+    Workspace(String),
+    Priority(i32, usize),
+    Entry(usize),
+}
+
+enum Mode {
+    Browse,
+    Reply(String),
+}
+
+struct UiState {
+    entries: Vec<HotlistEntry>,
+    selected: usize,
+    preview: Vec<BufferLine>,
+    preview_pointer: Option<Pointer>,
+    preview_lines: usize,
+    mode: Mode,
+    status: String,
+}
+
+/* Restores the terminal, whatever happens in the ui loop */
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new(out: &mut Stdout) -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(TerminalGuard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+pub fn ui(sys: &mut System, flags: UiFlags) -> Result<()> {
+    if !is_weechat_running(sys) {
+        println!("Weechat is currently not running.");
+        return Ok(());
+    }
+
+    let mut connection = init_connection()?;
+    debug!("connection initiated");
+    let events = spawn_relay_reader(&connection)?;
+
+    connection.send_command(&Command {
+        id: None,
+        command: SyncCommand::AllBuffers(SyncAllBuffers {
+            buffers: false,
+            upgrade: false,
+            buffer: true,
+            nicklist: false,
+        }),
+    })?;
+    debug!("sync request sent");
+    connection.send_command(&build_hotlist_command(Some(HOTLIST_ID)))?;
+
+    let mut out = stdout();
+    let guard = TerminalGuard::new(&mut out)?;
+    let result = run_ui(&mut connection, &events, &mut out, &flags);
+    drop(guard);
+
+    let _ = connection.send_command(&Command {
+        id: None,
+        command: QuitCommand::default(),
+    });
+    result
+}
+
+fn spawn_relay_reader(connection: &Connection) -> Result<Receiver<RelayEvent>> {
+    let mut reader = Connection {
+        stream: connection.stream.try_clone()?,
+    };
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        match reader.get_message() {
+            Ok(message) => {
+                if sender.send(RelayEvent::Message(message)).is_err() {
+                    break;
+                }
+            }
+            Err(e) => {
+                debug!("{:?}", e);
+                let _ = sender.send(RelayEvent::Disconnected);
+                break;
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+fn run_ui(
+    connection: &mut Connection,
+    events: &Receiver<RelayEvent>,
+    out: &mut Stdout,
+    flags: &UiFlags,
+) -> Result<()> {
+    let mut state = UiState {
+        entries: vec![],
+        selected: 0,
+        preview: vec![],
+        preview_pointer: None,
+        preview_lines: flags.lines,
+        mode: Mode::Browse,
+        status: "loading...".to_string(),
+    };
+    let mut last_refresh = Instant::now();
+
+    loop {
+        loop {
+            match events.try_recv() {
+                Ok(RelayEvent::Message(message)) => {
+                    handle_relay_message(connection, &mut state, message)?
+                }
+                Ok(RelayEvent::Disconnected) => {
+                    state.status = "disconnected from weechat".to_string();
+                    break;
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+
+        if last_refresh.elapsed() > REFRESH_INTERVAL {
+            connection.send_command(&build_hotlist_command(Some(HOTLIST_ID)))?;
+            last_refresh = Instant::now();
+        }
+
+        draw(out, &state)?;
+
+        if event::poll(Duration::from_millis(200))? {
+            if let TermEvent::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !handle_key(connection, &mut state, key)? {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+fn handle_relay_message(
+    connection: &mut Connection,
+    state: &mut UiState,
+    message: Message,
+) -> Result<()> {
+    match (&message.id, message.objects.first()) {
+        (Identifier::Client(id), Some(Object::Inl(infolist))) if id == HOTLIST_ID.as_bytes() => {
+            let selected_pointer = selected_entry(state).map(|e| e.buffer_pointer.clone());
+            state.entries = order_entries(build_hotlist_entries(
+                &Some(infolist.clone()),
+                &get_active_mutes(),
+            ));
+            state.selected = selected_pointer
+                .and_then(|p| state.entries.iter().position(|e| e.buffer_pointer == p))
+                .unwrap_or(0)
+                .min(state.entries.len().saturating_sub(1));
+            state.status = format!("{} unread buffer(s)", state.entries.len());
+            request_preview(connection, state)?;
+        }
+        (Identifier::Client(id), Some(Object::Hda(hdata))) if id == LINES_ID.as_bytes() => {
+            let lines = build_buffer_lines(hdata, state.preview_lines);
+            // drop responses for a buffer which is not selected anymore
+            if lines.is_empty()
+                || lines.first().and_then(|l| l.buffer_pointer.as_ref())
+                    == state.preview_pointer.as_ref()
+            {
+                state.preview = lines;
+            }
+        }
+        (Identifier::Event(Event::BufferLineAdded), Some(Object::Hda(hdata))) => {
+            for line in build_line_data(hdata) {
+                if line.buffer_pointer.is_some() && line.buffer_pointer == state.preview_pointer {
+                    state.preview.push(line);
+                }
+            }
+            if state.preview.len() > state.preview_lines {
+                state
+                    .preview
+                    .drain(..state.preview.len() - state.preview_lines);
+            }
+            connection.send_command(&build_hotlist_command(Some(HOTLIST_ID)))?;
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/*
+* Returns false when the ui should exit
+*/
+fn handle_key(connection: &mut Connection, state: &mut UiState, key: KeyEvent) -> Result<bool> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return Ok(false);
+    }
+    match &mut state.mode {
+        Mode::Browse => match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Down | KeyCode::Char('j') if state.selected + 1 < state.entries.len() => {
+                state.selected += 1;
+                request_preview(connection, state)?;
+            }
+            KeyCode::Up | KeyCode::Char('k') if state.selected > 0 => {
+                state.selected -= 1;
+                request_preview(connection, state)?;
+            }
+            KeyCode::Char('r') => {
+                if let Some(entry) = selected_entry(state).cloned() {
+                    send_mark_buffer_read(connection, &entry.buffer_pointer)?;
+                    state.status = format!("marked {} as read", entry.buffer_name);
+                    connection.send_command(&build_hotlist_command(Some(HOTLIST_ID)))?;
+                }
+            }
+            KeyCode::Char('R') => {
+                connection.send_command(&build_hotlist_command(Some(HOTLIST_ID)))?;
+            }
+            KeyCode::Enter | KeyCode::Char('i') if selected_entry(state).is_some() => {
+                state.mode = Mode::Reply(String::new());
+            }
+            _ => (),
+        },
+        Mode::Reply(input) => match key.code {
+            KeyCode::Esc => state.mode = Mode::Browse,
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Enter => {
+                let input = input.clone();
+                state.mode = Mode::Browse;
+                if let Some(entry) = selected_entry(state).cloned() {
+                    if !input.is_empty() {
+                        connection.send_command(&build_buffer_input_command(
+                            None,
+                            &entry.buffer_pointer,
+                            &input,
+                        )?)?;
+                        state.status = format!("sent to {}", entry.buffer_name);
+                    }
+                }
+            }
+            _ => (),
+        },
+    }
+    Ok(true)
+}
+
+fn selected_entry(state: &UiState) -> Option<&HotlistEntry> {
+    state.entries.get(state.selected)
+}
+
+fn request_preview(connection: &mut Connection, state: &mut UiState) -> Result<()> {
+    let pointer = selected_entry(state).map(|e| e.buffer_pointer.clone());
+    if pointer != state.preview_pointer {
+        state.preview.clear();
+        state.preview_pointer = pointer;
+        if let Some(pointer) = &state.preview_pointer {
+            connection.send_command(&build_buffer_lines_command(
+                Some(LINES_ID),
+                pointer,
+                state.preview_lines,
+            ))?;
+        }
+    }
+    Ok(())
+}
+
+/*
+* Entries grouped by workspace, then by priority (highest first),
+* then oldest first
+*/
+fn order_entries(entries: Vec<HotlistEntry>) -> Vec<HotlistEntry> {
+    let mut entries = order_hotlist_entries(entries);
+    entries.sort_by(|a, b| {
+        get_buffer_workspace(&a.buffer_name).cmp(get_buffer_workspace(&b.buffer_name))
+    });
+    entries
+}
+
+fn build_rows(entries: &[HotlistEntry]) -> Vec<Row> {
+    let mut rows = vec![];
+    let mut current: Option<(&str, i32)> = None;
+    for (i, entry) in entries.iter().enumerate() {
+        let workspace = get_buffer_workspace(&entry.buffer_name);
+        if current.map(|(w, _)| w) != Some(workspace) {
+            rows.push(Row::Workspace(workspace.to_string()));
+        }
+        if current != Some((workspace, entry.priority)) {
+            let count = entries
+                .iter()
+                .filter(|e| {
+                    e.priority == entry.priority
+                        && get_buffer_workspace(&e.buffer_name) == workspace
+                })
+                .count();
+            rows.push(Row::Priority(entry.priority, count));
+        }
+        current = Some((workspace, entry.priority));
+        rows.push(Row::Entry(i));
+    }
+    rows
+}
+
+fn priority_label(priority: i32) -> (&'static str, Color) {
+    match priority {
+        3 => ("highlight", Color::DarkYellow),
+        2 => ("private", Color::Magenta),
+        _ => ("thread", Color::Green),
+    }
+}
+
+fn short_buffer_name(buffer_name: &str) -> &str {
+    let workspace = get_buffer_workspace(buffer_name);
+    let name = buffer_name.strip_prefix("slack.").unwrap_or(buffer_name);
+    name.strip_prefix(workspace)
+        .and_then(|n| n.strip_prefix('.'))
+        .unwrap_or(name)
+}
+
+fn fit(text: &str, width: usize) -> String {
+    let mut fitted: String = text.chars().take(width).collect();
+    let len = fitted.chars().count();
+    fitted.extend(std::iter::repeat_n(' ', width - len));
+    fitted
+}
+
+fn draw(out: &mut Stdout, state: &UiState) -> Result<()> {
+    let (width, height) = terminal::size()?;
+    let (width, height) = (width as usize, height as usize);
+    let list_width = (width * 2 / 5).max(20).min(width.saturating_sub(3));
+    let preview_width = width.saturating_sub(list_width + 3);
+    let body_height = height.saturating_sub(1);
+
+    let rows = build_rows(&state.entries);
+    let selected_row = rows
+        .iter()
+        .position(|r| matches!(r, Row::Entry(i) if *i == state.selected))
+        .unwrap_or(0);
+    let scroll = (selected_row + 1).saturating_sub(body_height);
+
+    let mut preview_rows: Vec<String> = vec![];
+    for line in state.preview.iter() {
+        let text = format!(
+            "{} {} | {}",
+            format_local_time(line.date, false),
+            line.prefix,
+            line.message
+        );
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in chars.chunks(preview_width.max(1)) {
+            preview_rows.push(chunk.iter().collect());
+        }
+    }
+    let preview_scroll = preview_rows.len().saturating_sub(body_height);
+
+    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+    for y in 0..body_height {
+        queue!(out, cursor::MoveTo(0, y as u16))?;
+        match rows.get(y + scroll) {
+            Some(Row::Workspace(workspace)) => queue!(
+                out,
+                SetAttribute(Attribute::Bold),
+                Print(fit(workspace, list_width)),
+                SetAttribute(Attribute::Reset)
+            )?,
+            Some(Row::Priority(priority, count)) => {
+                let (label, color) = priority_label(*priority);
+                queue!(
+                    out,
+                    SetForegroundColor(color),
+                    Print(fit(&format!("  {label} ({count})"), list_width)),
+                    ResetColor
+                )?
+            }
+            Some(Row::Entry(i)) => {
+                let text = fit(
+                    &format!("    {}", short_buffer_name(&state.entries[*i].buffer_name)),
+                    list_width,
+                );
+                if *i == state.selected {
+                    queue!(
+                        out,
+                        SetAttribute(Attribute::Reverse),
+                        Print(text),
+                        SetAttribute(Attribute::Reset)
+                    )?
+                } else {
+                    queue!(out, Print(text))?
+                }
+            }
+            None => queue!(out, Print(fit("", list_width)))?,
+        }
+        queue!(out, Print(" │ "))?;
+        if let Some(row) = preview_rows.get(y + preview_scroll) {
+            queue!(out, Print(row))?;
+        }
+    }
+
+    let footer = match &state.mode {
+        Mode::Browse => format!(
+            "{} | j/k: move  r: mark read  enter: reply  R: refresh  q: quit",
+            state.status
+        ),
+        Mode::Reply(input) => format!("reply> {input}"),
+    };
+    queue!(
+        out,
+        cursor::MoveTo(0, body_height as u16),
+        SetAttribute(Attribute::Reverse),
+        Print(fit(&footer, width)),
+        SetAttribute(Attribute::Reset)
+    )?;
+    out.flush()?;
+    Ok(())
+}